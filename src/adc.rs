@@ -1,5 +1,11 @@
-use crate::interrupt::CoreInterrupt;
 pub use crate::raw::adc::cfg::{ClkDiv, PgaGain as Gain};
+use crate::{
+    gpio::{
+        gpioa::{PA0, PA1, PA12, PA13, PA14, PA15, PA2, PA3, PA4, PA5, PA6, PA7, PA8, PA9},
+        Floating, Input,
+    },
+    interrupt::CoreInterrupt,
+};
 use core::{future::poll_fn, task::Poll};
 use embassy_sync::waitqueue::AtomicWaker;
 
@@ -27,6 +33,35 @@ impl AdcChannel for Temperature {
     }
 }
 
+macro_rules! pin {
+    ($($PXi:ident: $channel:literal,)+) => {
+        $(
+            impl AdcChannel for $PXi<Input<Floating>> {
+                fn channel(&self) -> u8 {
+                    $channel
+                }
+            }
+        )+
+    };
+}
+
+pin!(
+    PA4: 0,
+    PA5: 1,
+    PA12: 2,
+    PA13: 3,
+    PA14: 4,
+    PA15: 5,
+    PA3: 6,
+    PA2: 7,
+    PA1: 8,
+    PA0: 9,
+    PA6: 10,
+    PA7: 11,
+    PA8: 12,
+    PA9: 13,
+);
+
 impl Adc {
     pub fn new(raw: crate::raw::Adc, gain: Gain, clk_div: ClkDiv) -> Self {
         raw.cfg().write(|w| {
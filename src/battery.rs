@@ -0,0 +1,113 @@
+use crate::{interrupt::CoreInterrupt, pfic::PficExt, sys::with_safe_access_mode, Pfic, Sys};
+use core::{future::poll_fn, task::Poll};
+use embassy_sync::waitqueue::AtomicWaker;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+// the monitor keeps running during sleep, while the detector is more accurate
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Threshold {
+    Monitor1V8 = 0x80,
+    Monitor1V9,
+    Monitor2V0,
+    Monitor2V1,
+    Monitor2V2,
+    Monitor2V3,
+    Monitor2V4,
+    Monitor2V5,
+    Detector1V9 = 0x00,
+    Detector2V1,
+    Detector2V3,
+    Detector2V5,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Status {
+    Normal,
+    /// Below the threshold.
+    Low,
+    /// Further below the threshold, a brown-out is imminent.
+    Lower,
+}
+
+pub struct BatteryMonitor<'a> {
+    sys: &'a Sys,
+}
+
+impl<'a> BatteryMonitor<'a> {
+    pub fn new(sys: &'a Sys, pfic: &Pfic, threshold: Threshold) -> Self {
+        let threshold = threshold as u8;
+        let monitor = threshold & 0x80 != 0;
+        with_safe_access_mode(|| {
+            // the enable bit of the detector selects the threshold range of the monitor
+            sys.bat_det_ctrl().write(|w| {
+                w.bat_det_en()
+                    .bit(!monitor || threshold & 0b100 != 0)
+                    .bat_mon_en()
+                    .bit(monitor)
+            });
+            sys.bat_det_cfg()
+                .write(|w| unsafe { w.bat_low_vth().bits(threshold & 0b11) });
+        });
+
+        pfic.enable(CoreInterrupt::WDOG_BAT, None);
+
+        Self { sys }
+    }
+
+    pub fn free(self) {
+        with_safe_access_mode(|| {
+            self.sys.bat_det_ctrl().reset();
+        });
+    }
+
+    pub fn status(&self) -> Status {
+        let bat_status = self.sys.bat_status().read();
+        if bat_status.bat_stat_lower().bit() {
+            Status::Lower
+        } else if bat_status.bat_stat_low().bit() {
+            Status::Low
+        } else {
+            Status::Normal
+        }
+    }
+
+    pub async fn wait_low(&mut self) {
+        self.wait(Status::Low).await
+    }
+
+    pub async fn wait_lower(&mut self) {
+        self.wait(Status::Lower).await
+    }
+
+    async fn wait(&mut self, status: Status) {
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if self.status() as u8 >= status as u8 {
+                Poll::Ready(())
+            } else {
+                with_safe_access_mode(|| {
+                    self.sys.bat_det_ctrl().modify(|_, w| match status {
+                        Status::Lower => w.bat_lower_ie().set_bit(),
+                        _ => w.bat_low_ie().set_bit(),
+                    });
+                });
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+// shares the interrupt with the watchdog
+pub(crate) fn on_interrupt(sys: &Sys) {
+    let bat_status = sys.bat_status().read();
+    if bat_status.bat_stat_low().bit() || bat_status.bat_stat_lower().bit() {
+        // will be handled later
+        with_safe_access_mode(|| {
+            sys.bat_det_ctrl()
+                .modify(|_, w| w.bat_low_ie().clear_bit().bat_lower_ie().clear_bit());
+        });
+        WAKER.wake();
+    }
+}
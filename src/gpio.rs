@@ -1,7 +1,605 @@
-use crate::interrupt::CoreInterrupt;
+use crate::{
+    interrupt::CoreInterrupt,
+    pfic::PficExt,
+    raw::gpioa::{Clr, Dir, IntEn, IntIf, IntMode, Out, PdDrv, Pin as PinReg, Pu},
+    Gpioa, Gpiob, Pfic, Sys,
+};
+use core::{convert::Infallible, future::poll_fn, marker::PhantomData, task::Poll};
+use embassy_sync::waitqueue::AtomicWaker;
+
+static GPIOA_WAKERS: [AtomicWaker; 16] = [const { AtomicWaker::new() }; 16];
+static GPIOB_WAKERS: [AtomicWaker; 16] = [const { AtomicWaker::new() }; 16];
+
+pub trait GpioExt {
+    type Parts;
+
+    fn split(self) -> Self::Parts;
+}
+
+pub struct Input<PULL> {
+    _pull: PhantomData<PULL>,
+}
+
+pub struct Floating;
+pub struct PullUp;
+pub struct PullDown;
+
+pub struct Output;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Drive {
+    /// 5mA
+    Standard,
+    /// 20mA
+    High,
+}
+
+pub struct Pin<P, const N: u8, MODE> {
+    _port: PhantomData<P>,
+    _mode: PhantomData<MODE>,
+}
+
+impl<P: Port, const N: u8, MODE> Pin<P, N, MODE> {
+    const MASK: u32 = 1 << N;
+
+    fn new() -> Self {
+        Self {
+            _port: PhantomData,
+            _mode: PhantomData,
+        }
+    }
+
+    pub fn into_floating_input(self) -> Pin<P, N, Input<Floating>> {
+        critical_section::with(|_| {
+            P::dir().modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+            P::pu().modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+            P::pd_drv().modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+        });
+        Pin::new()
+    }
+
+    pub fn into_pull_up_input(self) -> Pin<P, N, Input<PullUp>> {
+        critical_section::with(|_| {
+            P::dir().modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+            P::pd_drv().modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+            P::pu().modify(|r, w| unsafe { w.bits(r.bits() | Self::MASK) });
+        });
+        Pin::new()
+    }
+
+    pub fn into_pull_down_input(self) -> Pin<P, N, Input<PullDown>> {
+        critical_section::with(|_| {
+            P::dir().modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+            P::pu().modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+            P::pd_drv().modify(|r, w| unsafe { w.bits(r.bits() | Self::MASK) });
+        });
+        Pin::new()
+    }
+
+    pub fn into_push_pull_output(self, drive: Drive) -> Pin<P, N, Output> {
+        let mut pin = Pin::new();
+        critical_section::with(|_| {
+            P::pu().modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+            pin.set_drive(drive);
+            P::dir().modify(|r, w| unsafe { w.bits(r.bits() | Self::MASK) });
+        });
+        pin
+    }
+}
+
+impl<P: Port, const N: u8, PULL> Pin<P, N, Input<PULL>> {
+    pub fn is_high(&self) -> bool {
+        P::pin().read().bits() & Self::MASK != 0
+    }
+
+    pub fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+}
+
+impl<P: Port, const N: u8, PULL> Pin<P, N, Input<PULL>>
+where
+    Self: sealed::Interrupt,
+{
+    pub async fn wait_for_high(&mut self) {
+        if !self.is_high() {
+            wait_for::<P>(Self::MASK, N, false, true).await
+        }
+    }
+
+    pub async fn wait_for_low(&mut self) {
+        if !self.is_low() {
+            wait_for::<P>(Self::MASK, N, false, false).await
+        }
+    }
+
+    pub async fn wait_for_rising_edge(&mut self) {
+        wait_for::<P>(Self::MASK, N, true, true).await
+    }
+
+    pub async fn wait_for_falling_edge(&mut self) {
+        wait_for::<P>(Self::MASK, N, true, false).await
+    }
+
+    pub async fn wait_for_any_edge(&mut self) {
+        // there is no trigger on both edges, therefore wait for the edge leaving the current level
+        wait_for::<P>(Self::MASK, N, true, self.is_low()).await
+    }
+}
+
+/// PB22 and PB23 take over the interrupts of PB8 and PB9, which are held while remapped.
+pub struct InterruptRemap<M8, M9> {
+    pb8: gpiob::PB8<M8>,
+    pb9: gpiob::PB9<M9>,
+}
+
+impl<M8, M9> InterruptRemap<M8, M9> {
+    pub fn new(pb8: gpiob::PB8<M8>, pb9: gpiob::PB9<M9>) -> Self {
+        critical_section::with(|_| {
+            // SAFETY: only modified inside of a critical section
+            unsafe { Sys::steal() }
+                .pin_alternate()
+                .modify(|_, w| w.pin_intx().set_bit());
+        });
+        Self { pb8, pb9 }
+    }
+
+    pub fn free(self) -> (gpiob::PB8<M8>, gpiob::PB9<M9>) {
+        critical_section::with(|_| {
+            // SAFETY: only modified inside of a critical section
+            unsafe { Sys::steal() }
+                .pin_alternate()
+                .modify(|_, w| w.pin_intx().clear_bit());
+        });
+        (self.pb8, self.pb9)
+    }
+}
+
+pub struct Remapped<'a, const N: u8, PULL> {
+    pin: Pin<Gpiob, N, Input<PULL>>,
+    _remap: PhantomData<&'a ()>,
+}
+
+impl<const N: u8, PULL> Remapped<'_, N, PULL> {
+    // PB22 and PB23 use the interrupt bits of PB8 and PB9
+    const INT_BIT: u8 = N - 14;
+
+    pub fn free(self) -> Pin<Gpiob, N, Input<PULL>> {
+        self.pin
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.pin.is_high()
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.pin.is_low()
+    }
+
+    pub async fn wait_for_high(&mut self) {
+        if !self.is_high() {
+            wait_for::<Gpiob>(1 << N, Self::INT_BIT, false, true).await
+        }
+    }
+
+    pub async fn wait_for_low(&mut self) {
+        if !self.is_low() {
+            wait_for::<Gpiob>(1 << N, Self::INT_BIT, false, false).await
+        }
+    }
+
+    pub async fn wait_for_rising_edge(&mut self) {
+        wait_for::<Gpiob>(1 << N, Self::INT_BIT, true, true).await
+    }
+
+    pub async fn wait_for_falling_edge(&mut self) {
+        wait_for::<Gpiob>(1 << N, Self::INT_BIT, true, false).await
+    }
+
+    pub async fn wait_for_any_edge(&mut self) {
+        wait_for::<Gpiob>(1 << N, Self::INT_BIT, true, self.is_low()).await
+    }
+}
+
+macro_rules! remapped {
+    ($($N:literal),+) => {
+        $(
+            impl<PULL> Pin<Gpiob, $N, Input<PULL>> {
+                pub fn into_remapped<M8, M9>(
+                    self,
+                    _remap: &InterruptRemap<M8, M9>,
+                ) -> Remapped<'_, $N, PULL> {
+                    Remapped {
+                        pin: self,
+                        _remap: PhantomData,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+remapped!(22, 23);
+
+async fn wait_for<P: Port>(mask: u32, int_bit: u8, edge: bool, high: bool) {
+    let int_mask = 1 << int_bit;
+    critical_section::with(|_| {
+        // the output register selects high level or rising edge for inputs
+        P::int_mode().modify(|r, w| unsafe {
+            w.bits(if edge {
+                r.bits() | int_mask
+            } else {
+                r.bits() & !int_mask
+            })
+        });
+        if high {
+            P::out().modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+        } else {
+            P::clr().write(|w| unsafe { w.bits(mask) });
+        }
+        P::int_if().write(|w| unsafe { w.bits(int_mask) });
+        P::int_en().modify(|r, w| unsafe { w.bits(r.bits() | int_mask) });
+    });
+    unsafe { Pfic::steal() }.enable(P::INTERRUPT, None);
+
+    poll_fn(|cx| {
+        P::wakers()[int_bit as usize].register(cx.waker());
+        if P::int_en().read().bits() & int_mask == 0 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+impl<P: Port, const N: u8> Pin<P, N, Output> {
+    pub fn set_high(&mut self) {
+        critical_section::with(|_| {
+            P::out().modify(|r, w| unsafe { w.bits(r.bits() | Self::MASK) });
+        });
+    }
+
+    pub fn set_low(&mut self) {
+        P::clr().write(|w| unsafe { w.bits(Self::MASK) });
+    }
+
+    pub fn is_set_high(&self) -> bool {
+        P::out().read().bits() & Self::MASK != 0
+    }
+
+    pub fn is_set_low(&self) -> bool {
+        !self.is_set_high()
+    }
+
+    pub fn set_drive(&mut self, drive: Drive) {
+        critical_section::with(|_| {
+            P::pd_drv().modify(|r, w| unsafe {
+                match drive {
+                    Drive::Standard => w.bits(r.bits() & !Self::MASK),
+                    Drive::High => w.bits(r.bits() | Self::MASK),
+                }
+            });
+        });
+    }
+}
+
+impl<P, const N: u8, MODE> hal::digital::ErrorType for Pin<P, N, MODE> {
+    type Error = Infallible;
+}
+
+impl<P: Port, const N: u8, PULL> hal::digital::InputPin for Pin<P, N, Input<PULL>> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(Pin::is_high(self))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(Pin::is_low(self))
+    }
+}
+
+impl<P: Port, const N: u8, PULL> embedded_hal_async::digital::Wait for Pin<P, N, Input<PULL>>
+where
+    Self: sealed::Interrupt,
+{
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        Pin::wait_for_high(self).await;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        Pin::wait_for_low(self).await;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        Pin::wait_for_rising_edge(self).await;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        Pin::wait_for_falling_edge(self).await;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        Pin::wait_for_any_edge(self).await;
+        Ok(())
+    }
+}
+
+impl<const N: u8, PULL> hal::digital::ErrorType for Remapped<'_, N, PULL> {
+    type Error = Infallible;
+}
+
+impl<const N: u8, PULL> embedded_hal_async::digital::Wait for Remapped<'_, N, PULL> {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        Remapped::wait_for_high(self).await;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        Remapped::wait_for_low(self).await;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        Remapped::wait_for_rising_edge(self).await;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        Remapped::wait_for_falling_edge(self).await;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        Remapped::wait_for_any_edge(self).await;
+        Ok(())
+    }
+}
+
+impl<P: Port, const N: u8> hal::digital::OutputPin for Pin<P, N, Output> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Pin::set_low(self);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Pin::set_high(self);
+        Ok(())
+    }
+}
+
+impl<P: Port, const N: u8> hal::digital::StatefulOutputPin for Pin<P, N, Output> {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(Pin::is_set_high(self))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(Pin::is_set_low(self))
+    }
+}
+
+mod sealed {
+    use crate::{
+        interrupt::CoreInterrupt,
+        raw::gpioa::{Clr, Dir, IntEn, IntIf, IntMode, Out, PdDrv, Pin, Pu},
+    };
+    use embassy_sync::waitqueue::AtomicWaker;
+
+    // pins with an interrupt of their own
+    pub trait Interrupt {}
+
+    // GPIOB has the same register layout as GPIOA, only located at a different
+    // offset
+    pub trait Port {
+        const INTERRUPT: CoreInterrupt;
+
+        fn wakers() -> &'static [AtomicWaker; 16];
+
+        fn int_en() -> &'static IntEn;
+
+        fn int_mode() -> &'static IntMode;
+
+        fn int_if() -> &'static IntIf;
+
+        fn dir() -> &'static Dir;
+
+        fn pin() -> &'static Pin;
+
+        fn out() -> &'static Out;
+
+        fn clr() -> &'static Clr;
+
+        fn pu() -> &'static Pu;
+
+        fn pd_drv() -> &'static PdDrv;
+    }
+}
+use sealed::Port;
+
+impl Port for Gpioa {
+    const INTERRUPT: CoreInterrupt = CoreInterrupt::GPIOA;
+
+    fn wakers() -> &'static [AtomicWaker; 16] {
+        &GPIOA_WAKERS
+    }
+
+    fn int_en() -> &'static IntEn {
+        unsafe { (*Gpioa::ptr()).int_en() }
+    }
+
+    fn int_mode() -> &'static IntMode {
+        unsafe { (*Gpioa::ptr()).int_mode() }
+    }
+
+    fn int_if() -> &'static IntIf {
+        unsafe { (*Gpioa::ptr()).int_if() }
+    }
+
+    fn dir() -> &'static Dir {
+        unsafe { (*Gpioa::ptr()).dir() }
+    }
+
+    fn pin() -> &'static PinReg {
+        unsafe { (*Gpioa::ptr()).pin() }
+    }
+
+    fn out() -> &'static Out {
+        unsafe { (*Gpioa::ptr()).out() }
+    }
+
+    fn clr() -> &'static Clr {
+        unsafe { (*Gpioa::ptr()).clr() }
+    }
+
+    fn pu() -> &'static Pu {
+        unsafe { (*Gpioa::ptr()).pu() }
+    }
+
+    fn pd_drv() -> &'static PdDrv {
+        unsafe { (*Gpioa::ptr()).pd_drv() }
+    }
+}
+
+impl Port for Gpiob {
+    const INTERRUPT: CoreInterrupt = CoreInterrupt::GPIOB;
+
+    fn wakers() -> &'static [AtomicWaker; 16] {
+        &GPIOB_WAKERS
+    }
+
+    fn int_en() -> &'static IntEn {
+        unsafe { &*((*Gpiob::ptr()).int_en().as_ptr() as *const IntEn) }
+    }
+
+    fn int_mode() -> &'static IntMode {
+        unsafe { &*((*Gpiob::ptr()).int_mode().as_ptr() as *const IntMode) }
+    }
+
+    fn int_if() -> &'static IntIf {
+        unsafe { &*((*Gpiob::ptr()).int_if().as_ptr() as *const IntIf) }
+    }
+
+    fn dir() -> &'static Dir {
+        unsafe { &*((*Gpiob::ptr()).dir().as_ptr() as *const Dir) }
+    }
+
+    fn pin() -> &'static PinReg {
+        unsafe { &*((*Gpiob::ptr()).pin().as_ptr() as *const PinReg) }
+    }
+
+    fn out() -> &'static Out {
+        unsafe { &*((*Gpiob::ptr()).out().as_ptr() as *const Out) }
+    }
+
+    fn clr() -> &'static Clr {
+        unsafe { &*((*Gpiob::ptr()).clr().as_ptr() as *const Clr) }
+    }
+
+    fn pu() -> &'static Pu {
+        unsafe { &*((*Gpiob::ptr()).pu().as_ptr() as *const Pu) }
+    }
+
+    fn pd_drv() -> &'static PdDrv {
+        unsafe { &*((*Gpiob::ptr()).pd_drv().as_ptr() as *const PdDrv) }
+    }
+}
+
+macro_rules! port {
+    ($Gpio:ident, $gpio:ident, [$($PXi:ident: ($pxi:ident, $i:expr),)+], [$($int:literal),+]) => {
+        pub mod $gpio {
+            use super::{Floating, GpioExt, Input, Pin};
+            use crate::$Gpio;
+
+            pub struct Parts {
+                $(pub $pxi: $PXi<Input<Floating>>,)+
+            }
+
+            impl GpioExt for $Gpio {
+                type Parts = Parts;
+
+                fn split(self) -> Parts {
+                    Parts {
+                        $($pxi: Pin::new(),)+
+                    }
+                }
+            }
+
+            $(pub type $PXi<MODE> = Pin<$Gpio, $i, MODE>;)+
+        }
+
+        $(impl<MODE> sealed::Interrupt for Pin<$Gpio, $int, MODE> {})+
+    };
+}
+
+port!(Gpioa, gpioa, [
+    PA0: (pa0, 0),
+    PA1: (pa1, 1),
+    PA2: (pa2, 2),
+    PA3: (pa3, 3),
+    PA4: (pa4, 4),
+    PA5: (pa5, 5),
+    PA6: (pa6, 6),
+    PA7: (pa7, 7),
+    PA8: (pa8, 8),
+    PA9: (pa9, 9),
+    PA10: (pa10, 10),
+    PA11: (pa11, 11),
+    PA12: (pa12, 12),
+    PA13: (pa13, 13),
+    PA14: (pa14, 14),
+    PA15: (pa15, 15),
+], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+
+port!(Gpiob, gpiob, [
+    PB0: (pb0, 0),
+    PB1: (pb1, 1),
+    PB2: (pb2, 2),
+    PB3: (pb3, 3),
+    PB4: (pb4, 4),
+    PB5: (pb5, 5),
+    PB6: (pb6, 6),
+    PB7: (pb7, 7),
+    PB8: (pb8, 8),
+    PB9: (pb9, 9),
+    PB10: (pb10, 10),
+    PB11: (pb11, 11),
+    PB12: (pb12, 12),
+    PB13: (pb13, 13),
+    PB14: (pb14, 14),
+    PB15: (pb15, 15),
+    PB16: (pb16, 16),
+    PB17: (pb17, 17),
+    PB18: (pb18, 18),
+    PB19: (pb19, 19),
+    PB20: (pb20, 20),
+    PB21: (pb21, 21),
+    PB22: (pb22, 22),
+    PB23: (pb23, 23),
+], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+
+fn on_interrupt<P: Port>() {
+    let pending = P::int_if().read().bits() & P::int_en().read().bits();
+
+    // will be handled later
+    P::int_en().modify(|r, w| unsafe { w.bits(r.bits() & !pending) });
+    P::int_if().write(|w| unsafe { w.bits(pending) });
+
+    for (i, waker) in P::wakers().iter().enumerate() {
+        if pending & (1 << i) != 0 {
+            waker.wake();
+        }
+    }
+}
 
 #[riscv_rt::core_interrupt(CoreInterrupt::GPIOA)]
-fn gpioa() {}
+fn gpioa() {
+    on_interrupt::<Gpioa>();
+}
 
 #[riscv_rt::core_interrupt(CoreInterrupt::GPIOB)]
-fn gpiob() {}
+fn gpiob() {
+    on_interrupt::<Gpiob>();
+}
@@ -0,0 +1,852 @@
+use crate::{
+    interrupt::CoreInterrupt,
+    pfic::PficExt,
+    raw::i2c::star1,
+    remap::{I2cPins, Remap},
+    sys::SysExt,
+    Pfic, Sys,
+};
+use core::{future::poll_fn, task::Poll};
+use embassy_futures::block_on;
+use embassy_sync::waitqueue::AtomicWaker;
+use embassy_time::{with_timeout, Duration};
+use hal::i2c::{NoAcknowledgeSource, Operation, SevenBitAddress, TenBitAddress};
+
+const TIMEOUT: Duration = Duration::from_millis(10);
+
+const BERR: u16 = 1 << 8;
+const ARLO: u16 = 1 << 9;
+const AF: u16 = 1 << 10;
+const OVR: u16 = 1 << 11;
+const PECERR: u16 = 1 << 12;
+const TIMEOUT_FLAG: u16 = 1 << 13;
+const SMBALERT: u16 = 1 << 15;
+
+const BLOCK_MAX_LEN: usize = 32;
+const ALERT_RESPONSE_ADDRESS: u8 = 0x0C;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Speed {
+    /// 100 kHz
+    Standard,
+    /// 400 kHz
+    Fast,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    Bus,
+    ArbitrationLoss,
+    NoAcknowledge(NoAcknowledgeSource),
+    Overrun,
+    Timeout,
+    Pec,
+    /// The block is longer than 32 bytes or than the buffer.
+    BlockLength,
+}
+
+impl hal::i2c::Error for Error {
+    fn kind(&self) -> hal::i2c::ErrorKind {
+        match *self {
+            Self::Bus => hal::i2c::ErrorKind::Bus,
+            Self::ArbitrationLoss => hal::i2c::ErrorKind::ArbitrationLoss,
+            Self::NoAcknowledge(source) => hal::i2c::ErrorKind::NoAcknowledge(source),
+            Self::Overrun => hal::i2c::ErrorKind::Overrun,
+            Self::Timeout | Self::Pec | Self::BlockLength => hal::i2c::ErrorKind::Other,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Address {
+    Seven(u8),
+    Ten(u16),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Flag {
+    StartBit,
+    Address,
+    Address10,
+    ByteTransferFinished,
+    RxNotEmpty,
+    TxEmpty,
+}
+
+impl Flag {
+    fn is_set(self, star1: &star1::R) -> bool {
+        match self {
+            Self::StartBit => star1.sb().bit(),
+            Self::Address => star1.addr().bit(),
+            Self::Address10 => star1.add10().bit(),
+            Self::ByteTransferFinished => star1.btf().bit(),
+            Self::RxNotEmpty => star1.rx_ne().bit(),
+            Self::TxEmpty => star1.tx_e().bit(),
+        }
+    }
+}
+
+pub struct I2c {
+    raw: crate::raw::I2c,
+    pins: I2cPins,
+    // number of status polls before a blocking transfer gives up
+    timeout: u32,
+    // the PEC is appended to the last run of a transaction
+    pec: bool,
+}
+
+impl I2c {
+    pub fn new(raw: crate::raw::I2c, pins: I2cPins, sys: &Sys, pfic: &Pfic, speed: Speed) -> Self {
+        pins.remap();
+
+        let fsys = sys.fsys();
+        let mhz = fsys / 1_000_000;
+        reset(&raw, fsys);
+        match speed {
+            Speed::Standard => {
+                let ccr = (fsys / (100_000 * 2)).max(4);
+                raw.ckcfgr().write(|w| unsafe { w.ccr().bits(ccr as u16) });
+                raw.rtr()
+                    .write(|w| unsafe { w.trise().bits((mhz + 1).min(0x3F) as u8) });
+            }
+            Speed::Fast => {
+                let ccr = (fsys / (400_000 * 3)).max(1);
+                raw.ckcfgr()
+                    .write(|w| unsafe { w.ccr().bits(ccr as u16).f_s().set_bit() });
+                raw.rtr()
+                    .write(|w| unsafe { w.trise().bits((mhz * 300 / 1000 + 1) as u8) });
+            }
+        }
+        raw.oaddr1().write(|w| w.must1().set_bit());
+        raw.ctrl1().write(|w| w.pe().set_bit());
+
+        pfic.enable(CoreInterrupt::I2C, None);
+
+        Self {
+            raw,
+            pins,
+            // roughly 10 ms worth of polling
+            timeout: fsys / 1000,
+            pec: false,
+        }
+    }
+
+    pub fn free(self) -> (crate::raw::I2c, I2cPins) {
+        reset(&self.raw, 0);
+        (self.raw, self.pins)
+    }
+
+    // returns whether the flag is set
+    fn check(&self, flag: Flag) -> Result<bool, Error> {
+        let star1 = self.raw.star1().read();
+        if star1.af().bit() {
+            clear_flags(&self.raw, AF);
+            return Err(Error::NoAcknowledge(match flag {
+                Flag::Address | Flag::Address10 => NoAcknowledgeSource::Address,
+                _ => NoAcknowledgeSource::Data,
+            }));
+        }
+        if let Some(error) = bus_error(&self.raw, &star1) {
+            return Err(error);
+        }
+        Ok(flag.is_set(&star1))
+    }
+
+    async fn wait(&self, flag: Flag, blocking: bool) -> Result<(), Error> {
+        if blocking {
+            for _ in 0..self.timeout {
+                if self.check(flag)? {
+                    return Ok(());
+                }
+            }
+            return Err(Error::Timeout);
+        }
+
+        let wait = poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            match self.check(flag) {
+                Ok(true) => Poll::Ready(Ok(())),
+                Ok(false) => {
+                    enable_interrupts(&self.raw);
+                    Poll::Pending
+                }
+                Err(error) => Poll::Ready(Err(error)),
+            }
+        });
+        with_timeout(TIMEOUT, wait)
+            .await
+            .unwrap_or(Err(Error::Timeout))
+    }
+
+    // the address flag is left set, as the receiver has to configure the ACK before clearing it
+    async fn start(&self, address: Address, read: bool, blocking: bool) -> Result<(), Error> {
+        self.raw.ctrl1().modify(|_, w| w.start().set_bit());
+        self.wait(Flag::StartBit, blocking).await?;
+
+        match address {
+            Address::Seven(address) => {
+                self.write_data(address << 1 | read as u8);
+                self.wait(Flag::Address, blocking).await?;
+            }
+            Address::Ten(address) => {
+                let header = 0b1111_0000 | (address >> 7) as u8 & 0b110;
+                self.write_data(header);
+                self.wait(Flag::Address10, blocking).await?;
+                self.write_data(address as u8);
+                self.wait(Flag::Address, blocking).await?;
+
+                // the direction can only be changed with a repeated start and the header
+                if read {
+                    self.clear_address();
+                    self.raw.ctrl1().modify(|_, w| w.start().set_bit());
+                    self.wait(Flag::StartBit, blocking).await?;
+                    self.write_data(header | 1);
+                    self.wait(Flag::Address, blocking).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear_address(&self) {
+        self.raw.star1().read();
+        self.raw.star2().read();
+    }
+
+    fn write_data(&self, byte: u8) {
+        self.raw.datar().write(|w| unsafe { w.datar().bits(byte) });
+    }
+
+    // a run is either followed by a stop or a repeated start
+    fn end(&self, last: bool) {
+        if last {
+            self.raw
+                .ctrl1()
+                .modify(|_, w| w.stop().set_bit().pec().bit(self.pec));
+        } else {
+            self.raw.ctrl1().modify(|_, w| w.start().set_bit());
+        }
+    }
+
+    async fn write_run(
+        &self,
+        operations: &[Operation<'_>],
+        last: bool,
+        blocking: bool,
+    ) -> Result<(), Error> {
+        self.clear_address();
+        let bytes = operations.iter().flat_map(|operation| match operation {
+            Operation::Write(buffer) => buffer.iter(),
+            Operation::Read(..) => [].iter(),
+        });
+        let mut empty = true;
+        for &byte in bytes {
+            self.wait(Flag::TxEmpty, blocking).await?;
+            self.write_data(byte);
+            empty = false;
+        }
+        if !empty {
+            // the PEC is sent after the last byte once it has been moved to the shift register
+            if self.pec && last {
+                self.wait(Flag::TxEmpty, blocking).await?;
+                self.raw.ctrl1().modify(|_, w| w.pec().set_bit());
+            }
+            self.wait(Flag::ByteTransferFinished, blocking).await?;
+        }
+        if last {
+            self.raw.ctrl1().modify(|_, w| w.stop().set_bit());
+        }
+
+        Ok(())
+    }
+
+    async fn read_run(
+        &self,
+        operations: &mut [Operation<'_>],
+        last: bool,
+        blocking: bool,
+    ) -> Result<(), Error> {
+        let len = operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Read(buffer) => buffer.len(),
+                Operation::Write(..) => 0,
+            })
+            .sum::<usize>();
+        let mut pec = 0;
+        let pec = (self.pec && last && len > 0).then_some(&mut pec);
+        let len = len + pec.is_some() as usize;
+        self.raw.ctrl1().modify(|_, w| w.ack().bit(len > 1));
+        self.clear_address();
+
+        let bytes = operations
+            .iter_mut()
+            .flat_map(|operation| match operation {
+                Operation::Read(buffer) => buffer.iter_mut(),
+                Operation::Write(..) => [].iter_mut(),
+            })
+            .chain(pec);
+        self.receive(bytes, len, last, blocking).await
+    }
+
+    // the last byte is NACKed, or checked against the calculated PEC
+    async fn receive<'a>(
+        &self,
+        bytes: impl Iterator<Item = &'a mut u8>,
+        len: usize,
+        last: bool,
+        blocking: bool,
+    ) -> Result<(), Error> {
+        if len <= 1 {
+            self.end(last);
+        }
+        for (i, byte) in bytes.enumerate() {
+            // NACK the last byte
+            if len > 1 && i == len - 1 {
+                self.raw.ctrl1().modify(|_, w| w.ack().clear_bit());
+                self.end(last);
+            }
+            self.wait(Flag::RxNotEmpty, blocking).await?;
+            *byte = self.raw.datar().read().datar().bits();
+        }
+
+        // a repeated start is waited for by the next run
+        if last {
+            if let Some(error) = bus_error(&self.raw, &self.raw.star1().read()) {
+                return Err(error);
+            }
+            for _ in 0..self.timeout {
+                if self.raw.ctrl1().read().stop().bit_is_clear() {
+                    return Ok(());
+                }
+            }
+            return Err(Error::Timeout);
+        }
+
+        Ok(())
+    }
+
+    // adjacent operations of the same kind are merged, a change of direction issues a repeated
+    // start
+    async fn transact(
+        &mut self,
+        address: Address,
+        mut operations: &mut [Operation<'_>],
+        blocking: bool,
+    ) -> Result<(), Error> {
+        while let Some(first) = operations.first() {
+            let read = matches!(first, Operation::Read(..));
+            let len = operations
+                .iter()
+                .take_while(|operation| matches!(operation, Operation::Read(..)) == read)
+                .count();
+            let (run, rest) = operations.split_at_mut(len);
+            let last = rest.is_empty();
+
+            let result = match self.start(address, read, blocking).await {
+                Ok(()) if read => self.read_run(run, last, blocking).await,
+                Ok(()) => self.write_run(run, last, blocking).await,
+                Err(error) => Err(error),
+            };
+            if result.is_err() {
+                self.raw.ctrl1().modify(|_, w| w.stop().set_bit());
+                return result;
+            }
+
+            operations = rest;
+        }
+
+        Ok(())
+    }
+
+    // the length of an SMBus block read is given by its first byte
+    async fn block_read(
+        &mut self,
+        address: u8,
+        command: u8,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        let len = buffer.len();
+        let result = self.block_read_inner(address, command, buffer).await;
+        if result.is_err() {
+            self.raw.ctrl1().modify(|_, w| w.stop().set_bit());
+        }
+        // the transfer is completed before reporting a truncated block
+        match result? {
+            count if count > len => Err(Error::BlockLength),
+            count => Ok(count),
+        }
+    }
+
+    async fn block_read_inner(
+        &mut self,
+        address: u8,
+        command: u8,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        let address = Address::Seven(address);
+        self.start(address, false, false).await?;
+        self.write_run(&[Operation::Write(&[command])], false, false)
+            .await?;
+        self.start(address, true, false).await?;
+        self.raw.ctrl1().modify(|_, w| w.ack().set_bit());
+        self.clear_address();
+        self.wait(Flag::RxNotEmpty, false).await?;
+        let count = self.raw.datar().read().datar().bits() as usize;
+
+        // excess bytes are read, but dropped
+        let mut excess = [0; BLOCK_MAX_LEN];
+        let (buffer, excess) = if count <= buffer.len() {
+            (&mut buffer[..count], &mut excess[..0])
+        } else {
+            let excess_len = (count - buffer.len()).min(BLOCK_MAX_LEN);
+            (buffer, &mut excess[..excess_len])
+        };
+        let len = buffer.len();
+        let mut pec = 0;
+        let pec = self.pec.then_some(&mut pec);
+        let total = len + excess.len() + pec.is_some() as usize;
+        if total <= 1 {
+            self.raw.ctrl1().modify(|_, w| w.ack().clear_bit());
+        }
+        let bytes = buffer.iter_mut().chain(excess.iter_mut()).chain(pec);
+        self.receive(bytes, total, true, false).await?;
+
+        Ok(count)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// The controller writes to the matched address, 0 is the general call address
+    WriteRequest(u8),
+    /// The controller reads from the matched address
+    ReadRequest(u8),
+    Stop,
+}
+
+pub struct TargetConfig {
+    pub address: u8,
+    pub second_address: Option<u8>,
+    pub general_call: bool,
+    pub clock_stretching: bool,
+}
+
+impl Default for TargetConfig {
+    fn default() -> Self {
+        Self {
+            address: 0x42,
+            second_address: None,
+            general_call: false,
+            clock_stretching: true,
+        }
+    }
+}
+
+pub struct I2cTarget {
+    raw: crate::raw::I2c,
+    pins: I2cPins,
+}
+
+impl I2cTarget {
+    pub fn new(
+        raw: crate::raw::I2c,
+        pins: I2cPins,
+        sys: &Sys,
+        pfic: &Pfic,
+        config: TargetConfig,
+    ) -> Self {
+        pins.remap();
+
+        reset(&raw, sys.fsys());
+        raw.oaddr1()
+            .write(|w| unsafe { w.must1().set_bit().add7_1().bits(config.address) });
+        if let Some(address) = config.second_address {
+            raw.oaddr2()
+                .write(|w| unsafe { w.endual().set_bit().add2().bits(address) });
+        }
+        raw.ctrl1().write(|w| {
+            w.pe()
+                .set_bit()
+                .engc()
+                .bit(config.general_call)
+                .nostretch()
+                .bit(!config.clock_stretching)
+        });
+        // ACK can only be set once the peripheral is enabled
+        raw.ctrl1().modify(|_, w| w.ack().set_bit());
+
+        pfic.enable(CoreInterrupt::I2C, None);
+
+        Self { raw, pins }
+    }
+
+    pub fn free(self) -> (crate::raw::I2c, I2cPins) {
+        reset(&self.raw, 0);
+        (self.raw, self.pins)
+    }
+
+    pub async fn listen(&mut self) -> Result<Event, Error> {
+        wait_for(&self.raw, |star1| {
+            if star1.addr().bit() {
+                // the address flag is cleared by reading STAR2 after STAR1
+                let star2 = self.raw.star2().read();
+                let address = if star2.gencall().bit() {
+                    0
+                } else if star2.dualf().bit() {
+                    self.raw.oaddr2().read().add2().bits()
+                } else {
+                    self.raw.oaddr1().read().add7_1().bits()
+                };
+                Some(if star2.tra().bit() {
+                    Event::ReadRequest(address)
+                } else {
+                    Event::WriteRequest(address)
+                })
+            } else if star1.stopf().bit() {
+                // the stop flag is cleared by writing CTRL1 after reading STAR1
+                self.raw.ctrl1().modify(|_, w| w);
+                Some(Event::Stop)
+            } else {
+                // the controller NACKs the last byte of a read
+                if star1.af().bit() {
+                    clear_flags(&self.raw, AF);
+                }
+                None
+            }
+        })
+        .await
+    }
+
+    /// Receives bytes of a write request, returns early once the controller ends the transfer.
+    pub async fn read(&mut self, words: &mut [u8]) -> Result<usize, Error> {
+        for (i, word) in words.iter_mut().enumerate() {
+            let received = wait_for(&self.raw, |star1| {
+                if star1.rx_ne().bit() {
+                    Some(Some(self.raw.datar().read().datar().bits()))
+                } else if star1.stopf().bit() || star1.addr().bit() {
+                    Some(None)
+                } else {
+                    None
+                }
+            })
+            .await?;
+            match received {
+                Some(byte) => *word = byte,
+                None => return Ok(i),
+            }
+        }
+
+        Ok(words.len())
+    }
+
+    /// Sends bytes of a read request, returns early once the controller NACKs or ends the
+    /// transfer.
+    pub async fn write(&mut self, words: &[u8]) -> Result<usize, Error> {
+        for (i, &word) in words.iter().enumerate() {
+            let empty = wait_for(&self.raw, |star1| {
+                if star1.af().bit() {
+                    clear_flags(&self.raw, AF);
+                    Some(false)
+                } else if star1.tx_e().bit() {
+                    Some(true)
+                } else if star1.stopf().bit() || star1.addr().bit() {
+                    Some(false)
+                } else {
+                    None
+                }
+            })
+            .await?;
+            if !empty {
+                return Ok(i);
+            }
+            self.raw.datar().write(|w| unsafe { w.datar().bits(word) });
+        }
+
+        Ok(words.len())
+    }
+}
+
+/// SMBus host on top of the I2C controller, the PEC is appended to and checked for every command
+/// transferring data.
+pub struct Smbus {
+    i2c: I2c,
+}
+
+impl Smbus {
+    pub fn new(mut i2c: I2c, pec: bool) -> Self {
+        i2c.raw.ctrl1().modify(|_, w| w.pe().clear_bit());
+        i2c.raw.ctrl1().modify(|_, w| {
+            w.smbus()
+                .set_bit()
+                .smbtype()
+                .set_bit()
+                .enpec()
+                .bit(pec)
+                .pe()
+                .set_bit()
+        });
+        i2c.pec = pec;
+
+        Self { i2c }
+    }
+
+    pub fn free(mut self) -> I2c {
+        self.i2c.raw.ctrl1().modify(|_, w| w.pe().clear_bit());
+        self.i2c.raw.ctrl1().modify(|_, w| {
+            w.smbus()
+                .clear_bit()
+                .smbtype()
+                .clear_bit()
+                .enpec()
+                .clear_bit()
+                .pe()
+                .set_bit()
+        });
+        self.i2c.pec = false;
+        self.i2c
+    }
+
+    async fn transact(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        self.i2c
+            .transact(Address::Seven(address), operations, false)
+            .await
+    }
+
+    pub async fn quick_command(&mut self, address: u8, read: bool) -> Result<(), Error> {
+        let operation = if read {
+            Operation::Read(&mut [])
+        } else {
+            Operation::Write(&[])
+        };
+        self.transact(address, &mut [operation]).await
+    }
+
+    pub async fn send_byte(&mut self, address: u8, byte: u8) -> Result<(), Error> {
+        self.transact(address, &mut [Operation::Write(&[byte])])
+            .await
+    }
+
+    pub async fn receive_byte(&mut self, address: u8) -> Result<u8, Error> {
+        let mut byte = [0];
+        self.transact(address, &mut [Operation::Read(&mut byte)])
+            .await?;
+        Ok(byte[0])
+    }
+
+    pub async fn write_byte(&mut self, address: u8, command: u8, byte: u8) -> Result<(), Error> {
+        self.transact(address, &mut [Operation::Write(&[command, byte])])
+            .await
+    }
+
+    pub async fn read_byte(&mut self, address: u8, command: u8) -> Result<u8, Error> {
+        let mut byte = [0];
+        self.transact(
+            address,
+            &mut [Operation::Write(&[command]), Operation::Read(&mut byte)],
+        )
+        .await?;
+        Ok(byte[0])
+    }
+
+    pub async fn write_word(&mut self, address: u8, command: u8, word: u16) -> Result<(), Error> {
+        let [low, high] = word.to_le_bytes();
+        self.transact(address, &mut [Operation::Write(&[command, low, high])])
+            .await
+    }
+
+    pub async fn read_word(&mut self, address: u8, command: u8) -> Result<u16, Error> {
+        let mut word = [0; 2];
+        self.transact(
+            address,
+            &mut [Operation::Write(&[command]), Operation::Read(&mut word)],
+        )
+        .await?;
+        Ok(u16::from_le_bytes(word))
+    }
+
+    /// Writes at most 32 bytes, prefixed by their count.
+    pub async fn block_write(
+        &mut self,
+        address: u8,
+        command: u8,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        if data.len() > BLOCK_MAX_LEN {
+            return Err(Error::BlockLength);
+        }
+        self.transact(
+            address,
+            &mut [
+                Operation::Write(&[command, data.len() as u8]),
+                Operation::Write(data),
+            ],
+        )
+        .await
+    }
+
+    /// Reads a block of at most 32 bytes, returns the number of bytes stored in the buffer. A
+    /// block that doesn't fit into the buffer fills it and returns [`Error::BlockLength`].
+    pub async fn block_read(
+        &mut self,
+        address: u8,
+        command: u8,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.i2c.block_read(address, command, buffer).await
+    }
+
+    pub async fn wait_for_alert(&mut self) -> Result<(), Error> {
+        wait_for(&self.i2c.raw, |star1| {
+            star1
+                .smbalert()
+                .bit()
+                .then(|| clear_flags(&self.i2c.raw, SMBALERT))
+        })
+        .await
+    }
+
+    /// Returns the address of the device that asserted SMBALERT#.
+    pub async fn alert_response(&mut self) -> Result<u8, Error> {
+        let mut address = [0];
+        // the alert response doesn't carry a PEC
+        let pec = core::mem::replace(&mut self.i2c.pec, false);
+        let result = self
+            .transact(ALERT_RESPONSE_ADDRESS, &mut [Operation::Read(&mut address)])
+            .await;
+        self.i2c.pec = pec;
+        result?;
+        Ok(address[0] >> 1)
+    }
+}
+
+// the frequency is only required for the timings and can be left at 0 when disabling
+fn reset(raw: &crate::raw::I2c, fsys: u32) {
+    raw.ctrl1().write(|w| w.swrst().set_bit());
+    raw.ctrl1().write(|w| w);
+    raw.ctrl2()
+        .write(|w| unsafe { w.freq().bits((fsys / 1_000_000).min(0x3F) as u8) });
+}
+
+fn enable_interrupts(raw: &crate::raw::I2c) {
+    raw.ctrl2().modify(|_, w| {
+        w.itevten()
+            .set_bit()
+            .itbufen()
+            .set_bit()
+            .iterren()
+            .set_bit()
+    });
+}
+
+fn clear_flags(raw: &crate::raw::I2c, mask: u16) {
+    // SAFETY: the flags are cleared by writing 0, writing 1 has no effect
+    unsafe { raw.star1().as_ptr().write_volatile(!mask) };
+}
+
+// acknowledge failures are not reported, as they are part of a regular target transfer
+fn bus_error(raw: &crate::raw::I2c, star1: &star1::R) -> Option<Error> {
+    let (error, mask) = if star1.berr().bit() {
+        (Error::Bus, BERR)
+    } else if star1.arlo().bit() {
+        (Error::ArbitrationLoss, ARLO)
+    } else if star1.ovr().bit() {
+        (Error::Overrun, OVR)
+    } else if star1.timeout().bit() {
+        (Error::Timeout, TIMEOUT_FLAG)
+    } else if star1.pecerr().bit() {
+        (Error::Pec, PECERR)
+    } else {
+        return None;
+    };
+    clear_flags(raw, mask);
+    Some(error)
+}
+
+async fn wait_for<T>(
+    raw: &crate::raw::I2c,
+    mut f: impl FnMut(&star1::R) -> Option<T>,
+) -> Result<T, Error> {
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+        let star1 = raw.star1().read();
+        if let Some(error) = bus_error(raw, &star1) {
+            return Poll::Ready(Err(error));
+        }
+        match f(&star1) {
+            Some(value) => Poll::Ready(Ok(value)),
+            None => {
+                enable_interrupts(raw);
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+impl hal::i2c::ErrorType for I2c {
+    type Error = Error;
+}
+
+impl hal::i2c::I2c<SevenBitAddress> for I2c {
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        block_on(self.transact(Address::Seven(address), operations, true))
+    }
+}
+
+impl hal::i2c::I2c<TenBitAddress> for I2c {
+    fn transaction(
+        &mut self,
+        address: TenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        block_on(self.transact(Address::Ten(address), operations, true))
+    }
+}
+
+impl embedded_hal_async::i2c::I2c<SevenBitAddress> for I2c {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transact(Address::Seven(address), operations, false)
+            .await
+    }
+}
+
+impl embedded_hal_async::i2c::I2c<TenBitAddress> for I2c {
+    async fn transaction(
+        &mut self,
+        address: TenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transact(Address::Ten(address), operations, false)
+            .await
+    }
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::I2C)]
+fn i2c() {
+    // will be handled later
+    unsafe { crate::raw::I2c::steal() }.ctrl2().modify(|_, w| {
+        w.itevten()
+            .clear_bit()
+            .itbufen()
+            .clear_bit()
+            .iterren()
+            .clear_bit()
+    });
+    WAKER.wake();
+}
@@ -11,11 +11,23 @@ pub use raw::*;
 extern crate embedded_hal as hal;
 
 pub mod adc;
+pub mod battery;
 pub mod gpio;
+pub mod i2c;
+pub mod osc;
 pub mod pfic;
+pub mod power;
+pub mod pwm;
+pub mod remap;
+pub mod rtc;
+pub mod spi;
 pub mod sys;
 pub mod sysclk;
+pub mod timer;
+pub mod tkey;
+pub mod uart;
 pub mod usb;
+pub mod watchdog;
 
 struct CriticalSection;
 critical_section::set_impl!(CriticalSection);
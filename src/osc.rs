@@ -0,0 +1,115 @@
+use crate::{
+    sys::{with_safe_access_mode, SysExt},
+    Sys,
+};
+use embassy_time::{Duration, Timer};
+
+// the RTC expects 32768 Hz
+const LSI_FREQUENCY: u64 = 32768;
+const INT32K_TUNE_MAX: u16 = (1 << 13) - 1;
+// the total cycles of each mode are 1, 2, 4, 32, 64, 128, 1024 and 2047
+const OSC_CNT_TOTAL: u8 = 0b011;
+const CYCLES: u64 = 32;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LseCurrent {
+    Percent75,
+    Standard,
+    Percent150,
+    Percent200,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum HseCurrent {
+    Percent75,
+    Standard,
+    Percent125,
+    Percent150,
+}
+
+pub fn tune_lse(sys: &Sys, current: LseCurrent, load_pf: u8) {
+    // 12 to 27 pF in steps of 1 pF
+    let load = load_pf.clamp(12, 27) - 12;
+    with_safe_access_mode(|| {
+        sys.xt32k_tune().write(|w| unsafe {
+            w.xt32k_i_tune()
+                .bits(current as u8)
+                .xt32k_c_load()
+                .bits(load)
+        });
+    });
+}
+
+pub fn tune_hse(sys: &Sys, current: HseCurrent, load_pf: u8) {
+    // 10 to 24 pF in steps of 2 pF
+    let load = (load_pf.clamp(10, 24) - 10) / 2;
+    with_safe_access_mode(|| {
+        sys.xt32m_tune().write(|w| unsafe {
+            w.xt32m_i_bias()
+                .bits(current as u8)
+                .xt32m_c_load()
+                .bits(load)
+        });
+    });
+}
+
+/// Calibrates the internal 32 kHz oscillator against the system clock, which has to be derived
+/// from the HSE.
+pub fn calibrate_lsi(sys: &Sys) {
+    calibrate(sys, 1 << 12, 1 << 11);
+}
+
+/// Re-calibrates the internal 32 kHz oscillator every period, each calibration blocks for about
+/// 10 ms.
+pub async fn recalibrate_lsi(sys: &Sys, period: Duration) -> ! {
+    loop {
+        Timer::after(period).await;
+
+        let tune = sys.int32k_tune().read().int32k_tune().bits();
+        calibrate(sys, tune, 1 << 4);
+    }
+}
+
+// searches the tuning value around the given one with decreasing steps, a higher value increases
+// the frequency
+fn calibrate(sys: &Sys, mut tune: u16, mut step: u16) {
+    let expected = CYCLES * sys.fsys() as u64 / LSI_FREQUENCY;
+
+    with_safe_access_mode(|| {
+        sys.osc_cal_ctrl()
+            .write(|w| unsafe { w.osc_cnt_total().bits(OSC_CNT_TOTAL).osc_cnt_en().set_bit() });
+    });
+    while step > 0 {
+        with_safe_access_mode(|| {
+            sys.int32k_tune()
+                .write(|w| unsafe { w.int32k_tune().bits(tune) });
+        });
+        // the first capture after changing the tuning is discarded
+        capture(sys);
+        // a slow oscillator takes more system clocks
+        if capture(sys) > expected {
+            tune = (tune + step).min(INT32K_TUNE_MAX);
+        } else {
+            tune = tune.saturating_sub(step);
+        }
+        step /= 2;
+    }
+    with_safe_access_mode(|| {
+        sys.int32k_tune()
+            .write(|w| unsafe { w.int32k_tune().bits(tune) });
+        sys.osc_cal_ctrl().reset();
+    });
+}
+
+fn capture(sys: &Sys) -> u64 {
+    // the counter halts at the end of each capture until it's read
+    while sys.osc_cal_ctrl().read().osc_cnt_halt().bit_is_clear() {}
+    sys.osc_cal_cnt()
+        .write(|w| w.osc_cal_if().set_bit().osc_cal_ov_clr().set_bit());
+    while sys.osc_cal_ctrl().read().osc_cnt_halt().bit_is_set() {}
+    while sys.osc_cal_ctrl().read().osc_cnt_halt().bit_is_clear() {}
+
+    let count = sys.osc_cal_cnt().read().osc_cal_cnt().bits() as u64;
+    let overflows = sys.osc_cal_ov_cnt().read().osc_cal_ov_cnt().bits() as u64;
+    overflows * (1 << 14) + count
+}
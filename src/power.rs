@@ -0,0 +1,218 @@
+use crate::{
+    sys::{reset, set_clocksyssrc, with_safe_access_mode, ClockSysSrc},
+    Pfic, Sys,
+};
+use riscv::asm::{delay, nop, wfi};
+
+const PWR_RAM2K: u16 = 1 << 1;
+const PWR_CORE: u16 = 1 << 2;
+const PWR_EXTEND: u16 = 1 << 3;
+const PWR_RAM30K: u16 = 1 << 4;
+const PWR_DCDC: u16 = 1 << 9 | 1 << 10;
+const PWR_MUST_0010: u16 = 0b0010 << 11;
+const PWR_PLAN_EN: u16 = 1 << 15;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum WakeDelay {
+    Long,
+    Short,
+}
+
+#[derive(Copy, Clone)]
+pub struct Config {
+    pub rtc_wake: bool,
+    pub gpio_wake: bool,
+    pub usb_wake: bool,
+    pub bat_wake: bool,
+    pub wake_delay: WakeDelay,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rtc_wake: true,
+            gpio_wake: true,
+            usb_wake: false,
+            bat_wake: false,
+            wake_delay: WakeDelay::Short,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct Retention {
+    pub ram2k: bool,
+    pub ram30k: bool,
+    /// Keeps the USB and BLE peripherals powered.
+    pub extend: bool,
+}
+
+impl Retention {
+    fn bits(self) -> u16 {
+        (if self.ram2k { PWR_RAM2K } else { 0 })
+            | (if self.ram30k { PWR_RAM30K } else { 0 })
+            | (if self.extend { PWR_EXTEND } else { 0 })
+    }
+}
+
+pub struct Power<'a> {
+    sys: &'a Sys,
+}
+
+impl<'a> Power<'a> {
+    pub fn new(sys: &'a Sys, config: Config) -> Self {
+        with_safe_access_mode(|| {
+            sys.slp_wake_ctrl().write(|w| {
+                w.slp_rtc_wake()
+                    .bit(config.rtc_wake)
+                    .slp_gpio_wake()
+                    .bit(config.gpio_wake)
+                    .slp_usb_wake()
+                    .bit(config.usb_wake)
+                    .slp_usb2_wake()
+                    .bit(config.usb_wake)
+                    .slp_bat_wake()
+                    .bit(config.bat_wake)
+                    .wake_ev_mode()
+                    .set_bit()
+            });
+            sys.slp_power_ctrl()
+                .modify(|_, w| unsafe { w.wake_dly_mod().bits(config.wake_delay as u8) });
+        });
+
+        Self { sys }
+    }
+
+    /// Stops the core clock until the next interrupt.
+    pub fn idle(&mut self) {
+        enter(false);
+    }
+
+    /// Stops all clocks until one of the wake sources triggers, the system tick doesn't advance.
+    pub fn halt(&mut self) {
+        let clocks = self.prepare();
+        enter(true);
+        self.restore(clocks);
+    }
+
+    /// Powers down everything except the core and the retained RAM until one of the wake sources
+    /// triggers, the system tick doesn't advance.
+    pub fn sleep(&mut self, retention: Retention) {
+        let clocks = self.prepare();
+        let power_plan = self.sys.power_plan().read().bits() & PWR_DCDC
+            | PWR_PLAN_EN
+            | PWR_MUST_0010
+            | PWR_CORE
+            | retention.bits();
+        with_safe_access_mode(|| {
+            self.sys
+                .slp_power_ctrl()
+                .modify(|_, w| w.ram_ret_lv().set_bit());
+            self.sys
+                .power_plan()
+                .write(|w| unsafe { w.bits(power_plan) });
+        });
+        enter(true);
+        with_safe_access_mode(|| {
+            self.sys
+                .slp_power_ctrl()
+                .modify(|_, w| w.ram_ret_lv().clear_bit());
+        });
+        self.restore(clocks);
+    }
+
+    /// Powers down everything except the retained RAM, waking up resets the system.
+    pub fn shutdown(&mut self, retention: Retention) -> ! {
+        self.prepare();
+        let power_plan = PWR_PLAN_EN | PWR_MUST_0010 | retention.bits();
+        with_safe_access_mode(|| {
+            self.sys
+                .slp_power_ctrl()
+                .modify(|_, w| w.ram_ret_lv().set_bit());
+            self.sys
+                .power_plan()
+                .write(|w| unsafe { w.bits(power_plan) });
+        });
+        enter(true);
+        reset()
+    }
+
+    // lowers the system clock and the oscillator currents, the clock configuration is returned so
+    // that it can be restored after waking up
+    fn prepare(&self) -> Clocks {
+        let clocks = Clocks {
+            hfck_pwr_ctrl: self.sys.hfck_pwr_ctrl().read().bits(),
+            clk_sys_cfg: self.sys.clk_sys_cfg().read().bits(),
+            flash_cfg: self.sys.flash_cfg().read().bits(),
+            xt32m_tune: self.sys.xt32m_tune().read().bits(),
+            xt32k_tune: self.sys.xt32k_tune().read().bits(),
+        };
+
+        // the 32 MHz oscillator needs 150% of its rated current to start up quickly, and the 32 kHz
+        // oscillator only its rated current once it's stable
+        let lse_stable = self.sys.rtc_cnt_32k().read().bits() > 0x3FFF;
+        with_safe_access_mode(|| {
+            self.sys
+                .xt32m_tune()
+                .modify(|_, w| unsafe { w.xt32m_i_bias().bits(0b11) });
+            if lse_stable {
+                self.sys
+                    .xt32k_tune()
+                    .modify(|_, w| unsafe { w.xt32k_i_tune().bits(0b01) });
+            }
+        });
+        set_clocksyssrc(self.sys, ClockSysSrc::HSE(5));
+
+        clocks
+    }
+
+    fn restore(&self, clocks: Clocks) {
+        with_safe_access_mode(|| {
+            self.sys
+                .hfck_pwr_ctrl()
+                .write(|w| unsafe { w.bits(clocks.hfck_pwr_ctrl) });
+        });
+        delay(4000);
+        with_safe_access_mode(|| {
+            self.sys
+                .clk_sys_cfg()
+                .write(|w| unsafe { w.bits(clocks.clk_sys_cfg) });
+            nop();
+            nop();
+            nop();
+            nop();
+        });
+        with_safe_access_mode(|| {
+            self.sys
+                .flash_cfg()
+                .write(|w| unsafe { w.bits(clocks.flash_cfg) });
+            self.sys
+                .xt32m_tune()
+                .write(|w| unsafe { w.bits(clocks.xt32m_tune) });
+            self.sys
+                .xt32k_tune()
+                .write(|w| unsafe { w.bits(clocks.xt32k_tune) });
+        });
+    }
+}
+
+struct Clocks {
+    hfck_pwr_ctrl: u8,
+    clk_sys_cfg: u16,
+    flash_cfg: u8,
+    xt32m_tune: u8,
+    xt32k_tune: u8,
+}
+
+fn enter(deep: bool) {
+    // SAFETY: the sleep mode is only changed right before waiting for an interrupt
+    unsafe { Pfic::steal() }
+        .sctlr()
+        .modify(|_, w| w.sleepdeep().bit(deep).wfitowfe().clear_bit());
+    wfi();
+    nop();
+    nop();
+    unsafe { Pfic::steal() }
+        .sctlr()
+        .modify(|_, w| w.sleepdeep().clear_bit());
+}
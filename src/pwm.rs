@@ -0,0 +1,264 @@
+use crate::{
+    interrupt::CoreInterrupt,
+    pfic::PficExt,
+    raw::pwmx::RegisterBlock,
+    remap::{PwmxPins, Remap},
+    sys::SysExt,
+    Pfic, Pwmx, Sys,
+};
+use core::{convert::Infallible, future::poll_fn, marker::PhantomData, task::Poll};
+use embassy_sync::waitqueue::AtomicWaker;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Bits8,
+    Bits7,
+    Bits6,
+    Bits5,
+}
+
+impl Resolution {
+    // the cycle is one clock shorter than the resolution, so that the maximum duty cycle is always
+    // high
+    fn cycles(self) -> u32 {
+        255 >> self as u32
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+pub struct Config {
+    pub frequency: u32,
+    pub resolution: Resolution,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frequency: 10_000,
+            resolution: Resolution::Bits8,
+        }
+    }
+}
+
+pub struct Pwm {
+    raw: Pwmx,
+    pins: PwmxPins,
+}
+
+impl Pwm {
+    pub fn new(raw: Pwmx, pins: PwmxPins, sys: &Sys, pfic: &Pfic, config: Config) -> Self {
+        pins.remap();
+
+        let cycles = config.frequency.saturating_mul(config.resolution.cycles());
+        let clock_div = (sys.fsys() / cycles.max(1)).clamp(1, 256);
+        raw.pwm_out_en().reset();
+        // a divisor of 256 is written as 0
+        raw.pwm_clock_div()
+            .write(|w| unsafe { w.pwm_clock_div().bits(clock_div as u8) });
+        raw.pwm_config().write(|w| unsafe {
+            w.pwm_cycle_sel()
+                .set_bit()
+                .pwm_cyc_mod()
+                .bits(config.resolution as u8)
+        });
+
+        pfic.enable(CoreInterrupt::PWMx, None);
+
+        Self { raw, pins }
+    }
+
+    pub fn free(self) -> (Pwmx, PwmxPins) {
+        self.raw.pwm_out_en().reset();
+        self.raw.pwm_config().reset();
+        self.raw.pwm_int_ctrl().reset();
+        (self.raw, self.pins)
+    }
+
+    /// The channels borrow the PWM, so that it can only be freed after them.
+    pub fn split(&mut self) -> Channels<'_> {
+        Channels {
+            pwm4: Channel { _pwm: PhantomData },
+            pwm5: Channel { _pwm: PhantomData },
+            pwm6: Channel { _pwm: PhantomData },
+            pwm7: Channel { _pwm: PhantomData },
+            pwm8: Channel { _pwm: PhantomData },
+            pwm9: Channel { _pwm: PhantomData },
+            pwm10: Channel { _pwm: PhantomData },
+            pwm11: Channel { _pwm: PhantomData },
+            cycle: Cycle { _pwm: PhantomData },
+        }
+    }
+}
+
+pub struct Channels<'a> {
+    pub pwm4: Channel<'a, 4>,
+    pub pwm5: Channel<'a, 5>,
+    pub pwm6: Channel<'a, 6>,
+    pub pwm7: Channel<'a, 7>,
+    pub pwm8: Channel<'a, 8>,
+    pub pwm9: Channel<'a, 9>,
+    pub pwm10: Channel<'a, 10>,
+    pub pwm11: Channel<'a, 11>,
+    pub cycle: Cycle<'a>,
+}
+
+pub struct Channel<'a, const N: u8> {
+    _pwm: PhantomData<&'a mut Pwm>,
+}
+
+impl<const N: u8> Channel<'_, N> {
+    const MASK: u8 = 1 << (N - 4);
+
+    pub fn enable(&mut self) {
+        critical_section::with(|_| {
+            regs()
+                .pwm_out_en()
+                .modify(|r, w| unsafe { w.bits(r.bits() | Self::MASK) });
+        });
+    }
+
+    pub fn disable(&mut self) {
+        critical_section::with(|_| {
+            regs()
+                .pwm_out_en()
+                .modify(|r, w| unsafe { w.bits(r.bits() & !Self::MASK) });
+        });
+    }
+
+    pub fn set_polarity(&mut self, polarity: Polarity) {
+        critical_section::with(|_| {
+            regs().pwm_polar().modify(|r, w| unsafe {
+                match polarity {
+                    Polarity::ActiveHigh => w.bits(r.bits() & !Self::MASK),
+                    Polarity::ActiveLow => w.bits(r.bits() | Self::MASK),
+                }
+            });
+        });
+    }
+}
+
+impl<const N: u8> hal::pwm::ErrorType for Channel<'_, N> {
+    type Error = Infallible;
+}
+
+impl<const N: u8> hal::pwm::SetDutyCycle for Channel<'_, N> {
+    fn max_duty_cycle(&self) -> u16 {
+        255 >> regs().pwm_config().read().pwm_cyc_mod().bits()
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        let duty = duty.min(self.max_duty_cycle());
+        // SAFETY: the data registers of PWM4 to PWM11 are consecutive
+        unsafe {
+            regs()
+                .pwm4_data()
+                .as_ptr()
+                .add(N as usize - 4)
+                .write_volatile(duty as u8)
+        };
+        Ok(())
+    }
+}
+
+pub struct Pair<'a, const A: u8, const B: u8> {
+    first: Channel<'a, A>,
+    second: Channel<'a, B>,
+}
+
+impl<'a, const A: u8, const B: u8> Pair<'a, A, B> {
+    pub fn first(&mut self) -> &mut Channel<'a, A> {
+        &mut self.first
+    }
+
+    pub fn second(&mut self) -> &mut Channel<'a, B> {
+        &mut self.second
+    }
+
+    pub fn enable(&mut self) {
+        self.first.enable();
+        self.second.enable();
+    }
+
+    pub fn disable(&mut self) {
+        self.first.disable();
+        self.second.disable();
+    }
+}
+
+macro_rules! pair {
+    ($A:literal, $B:literal, $stag_en:ident) => {
+        impl<'a> Channel<'a, $A> {
+            /// Pairs the channel with the next one, the cycles alternate between both outputs.
+            pub fn into_staggered(self, second: Channel<'a, $B>) -> Pair<'a, $A, $B> {
+                critical_section::with(|_| {
+                    regs().pwm_config().modify(|_, w| w.$stag_en().set_bit());
+                });
+                Pair {
+                    first: self,
+                    second,
+                }
+            }
+        }
+
+        impl<'a> Pair<'a, $A, $B> {
+            pub fn free(self) -> (Channel<'a, $A>, Channel<'a, $B>) {
+                critical_section::with(|_| {
+                    regs().pwm_config().modify(|_, w| w.$stag_en().clear_bit());
+                });
+                (self.first, self.second)
+            }
+        }
+    };
+}
+
+pair!(4, 5, pwm4_5_stag_en);
+pair!(6, 7, pwm6_7_stag_en);
+pair!(8, 9, pwm8_9_stag_en);
+pair!(10, 11, pwm10_11_stag_en);
+
+pub struct Cycle<'a> {
+    _pwm: PhantomData<&'a mut Pwm>,
+}
+
+impl Cycle<'_> {
+    /// Duty cycles written before the end of the cycle are applied to the next one.
+    pub async fn wait_cycle_end(&mut self) {
+        let regs = regs();
+
+        // ignore the end of a previous cycle
+        regs.pwm_int_ctrl().modify(|_, w| w.pwm_if_cyc().set_bit());
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if regs.pwm_int_ctrl().read().pwm_if_cyc().bit() {
+                regs.pwm_int_ctrl().modify(|_, w| w.pwm_if_cyc().set_bit());
+                Poll::Ready(())
+            } else {
+                // writing 0 to the flag has no effect
+                regs.pwm_int_ctrl()
+                    .modify(|_, w| w.pwm_if_cyc().clear_bit().pwm_ie_cyc().set_bit());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+fn regs() -> &'static RegisterBlock {
+    unsafe { &*Pwmx::ptr() }
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::PWMx)]
+fn pwmx() {
+    // will be handled later
+    unsafe { Pwmx::steal() }
+        .pwm_int_ctrl()
+        .modify(|_, w| w.pwm_if_cyc().clear_bit().pwm_ie_cyc().clear_bit());
+    WAKER.wake();
+}
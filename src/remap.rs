@@ -0,0 +1,185 @@
+use crate::{
+    gpio::{
+        gpioa::{PA10, PA11, PA12, PA13, PA14, PA15, PA2, PA4, PA5, PA6, PA7, PA8, PA9},
+        gpiob::{
+            PB0, PB1, PB10, PB11, PB12, PB13, PB14, PB15, PB2, PB20, PB21, PB22, PB23, PB3, PB4,
+            PB5, PB6, PB7,
+        },
+        Floating, Input, Output, PullUp,
+    },
+    raw::sys::pin_alternate::W,
+    Sys,
+};
+
+pub trait Remap {
+    fn remap(&self);
+}
+
+fn modify(f: impl FnOnce(&mut W) -> &mut W) {
+    critical_section::with(|_| {
+        // SAFETY: only modified inside of a critical section
+        unsafe { Sys::steal() }.pin_alternate().modify(|_, w| f(w));
+    });
+}
+
+macro_rules! tmr_pin {
+    ($TmrPin:ident, $Default:ident, $Remapped:ident, $pin_tmr:ident) => {
+        pub enum $TmrPin<MODE> {
+            Default($Default<MODE>),
+            Remapped($Remapped<MODE>),
+        }
+
+        impl<MODE> Remap for $TmrPin<MODE> {
+            fn remap(&self) {
+                let remapped = matches!(self, Self::Remapped(..));
+                modify(|w| w.$pin_tmr().bit(remapped));
+            }
+        }
+    };
+}
+
+tmr_pin!(Tmr0Pin, PA9, PB23, pin_tmr0);
+tmr_pin!(Tmr1Pin, PA10, PB10, pin_tmr1);
+tmr_pin!(Tmr2Pin, PA11, PB11, pin_tmr2);
+tmr_pin!(Tmr3Pin, PA2, PB22, pin_tmr3);
+
+macro_rules! uart_pins {
+    (
+        $UartPins:ident,
+        ($Tx:ident, $Rx:ident),
+        ($TxRemapped:ident, $RxRemapped:ident),
+        $pin_uart:ident
+    ) => {
+        pub enum $UartPins {
+            Default {
+                tx: $Tx<Output>,
+                rx: $Rx<Input<PullUp>>,
+            },
+            Remapped {
+                tx: $TxRemapped<Output>,
+                rx: $RxRemapped<Input<PullUp>>,
+            },
+        }
+
+        impl Remap for $UartPins {
+            fn remap(&self) {
+                let remapped = matches!(self, Self::Remapped { .. });
+                modify(|w| w.$pin_uart().bit(remapped));
+            }
+        }
+    };
+}
+
+uart_pins!(Uart0Pins, (PB7, PB4), (PA14, PA15), pin_uart0);
+uart_pins!(Uart1Pins, (PA9, PA8), (PB13, PB12), pin_uart1);
+uart_pins!(Uart2Pins, (PA7, PA6), (PB23, PB22), pin_uart2);
+uart_pins!(Uart3Pins, (PA5, PA4), (PB21, PB20), pin_uart3);
+
+pub enum Spi0Pins<SCK, MOSI, MISO> {
+    Default {
+        sck: PA13<SCK>,
+        mosi: PA14<MOSI>,
+        miso: PA15<MISO>,
+    },
+    Remapped {
+        sck: PB13<SCK>,
+        mosi: PB14<MOSI>,
+        miso: PB15<MISO>,
+    },
+}
+
+impl<SCK, MOSI, MISO> Remap for Spi0Pins<SCK, MOSI, MISO> {
+    fn remap(&self) {
+        let remapped = matches!(self, Self::Remapped { .. });
+        modify(|w| w.pin_spi0().bit(remapped));
+    }
+}
+
+pub enum Spi0SlavePins {
+    Default {
+        scs: PA12<Input<PullUp>>,
+        sck: PA13<Input<Floating>>,
+        mosi: PA14<Input<Floating>>,
+        miso: PA15<Output>,
+    },
+    Remapped {
+        scs: PB12<Input<PullUp>>,
+        sck: PB13<Input<Floating>>,
+        mosi: PB14<Input<Floating>>,
+        miso: PB15<Output>,
+    },
+}
+
+impl Remap for Spi0SlavePins {
+    fn remap(&self) {
+        let remapped = matches!(self, Self::Remapped { .. });
+        modify(|w| w.pin_spi0().bit(remapped));
+    }
+}
+
+// PWM6, PWM10 and PWM11 can't be remapped
+pub enum PwmxPins {
+    Default {
+        pwm4: Option<PA12<Output>>,
+        pwm5: Option<PA13<Output>>,
+        pwm6: Option<PB0<Output>>,
+        pwm7: Option<PB4<Output>>,
+        pwm8: Option<PB6<Output>>,
+        pwm9: Option<PB7<Output>>,
+        pwm10: Option<PB14<Output>>,
+        pwm11: Option<PB23<Output>>,
+    },
+    Remapped {
+        pwm4: Option<PA6<Output>>,
+        pwm5: Option<PA7<Output>>,
+        pwm6: Option<PB0<Output>>,
+        pwm7: Option<PB1<Output>>,
+        pwm8: Option<PB2<Output>>,
+        pwm9: Option<PB3<Output>>,
+        pwm10: Option<PB14<Output>>,
+        pwm11: Option<PB23<Output>>,
+    },
+}
+
+impl Remap for PwmxPins {
+    fn remap(&self) {
+        let remapped = matches!(self, Self::Remapped { .. });
+        modify(|w| w.pin_pwmx().bit(remapped));
+    }
+}
+
+pub enum I2cPins {
+    Default {
+        scl: PB13<Input<PullUp>>,
+        sda: PB12<Input<PullUp>>,
+    },
+    Remapped {
+        scl: PB21<Input<PullUp>>,
+        sda: PB20<Input<PullUp>>,
+    },
+}
+
+impl Remap for I2cPins {
+    fn remap(&self) {
+        let remapped = matches!(self, Self::Remapped { .. });
+        modify(|w| w.pin_i2c().bit(remapped));
+    }
+}
+
+pub enum ModemPins {
+    Default {
+        dsr: PB1<Input<PullUp>>,
+        dtr: PB5<Output>,
+    },
+    Remapped {
+        dsr: PB14<Input<PullUp>>,
+        dtr: PB15<Output>,
+    },
+}
+
+impl Remap for ModemPins {
+    fn remap(&self) {
+        let remapped = matches!(self, Self::Remapped { .. });
+        modify(|w| w.pin_modem().bit(remapped));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{
+    interrupt::CoreInterrupt,
+    pfic::PficExt,
+    sys::{set_clock32ksrc, with_safe_access_mode, Clock32KSrc},
+    Pfic, Sys,
+};
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+use embassy_sync::waitqueue::AtomicWaker;
+use embassy_time::{Duration, TICK_HZ};
+
+// the day counter is only 14 bits wide and starts at 2020-01-01
+const EPOCH_DAYS: u32 = 18262;
+const DAY_MASK: u32 = (1 << 14) - 1;
+// the cycle counter runs at 32768 Hz and wraps every day
+const CYCLES_PER_SECOND: u32 = 32768;
+const CYCLES_PER_DAY: u32 = 86400 * CYCLES_PER_SECOND;
+
+static ALARM_WAKER: AtomicWaker = AtomicWaker::new();
+static PERIOD_WAKER: AtomicWaker = AtomicWaker::new();
+// the flags are cleared by the interrupt, so that a source nobody waits for doesn't keep it pending
+static ALARMED: AtomicBool = AtomicBool::new(false);
+static TICKED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Period {
+    Millis125,
+    Millis250,
+    Millis500,
+    Secs1,
+    Secs2,
+    Secs4,
+    Secs8,
+    Secs16,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    pub fn from_unix_timestamp(timestamp: u64) -> Self {
+        let days = (timestamp / 86400) as u32;
+        let seconds = (timestamp % 86400) as u32;
+
+        // civil from days, with eras starting at 0000-03-01
+        let days = days + 719468;
+        let era = days / 146097;
+        let day_of_era = days % 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_of_era = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_of_era + 2) / 5 + 1;
+        let month = if month_of_era < 10 {
+            month_of_era + 3
+        } else {
+            month_of_era - 9
+        };
+        let year = era * 400 + year_of_era + (month <= 2) as u32;
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+
+    /// Dates before 1970-01-01 are clamped, other fields out of their range carry over.
+    pub fn to_unix_timestamp(&self) -> u64 {
+        // days from civil, with eras starting at 0000-03-01
+        let month = self.month as i64;
+        let year = self.year as i64 - (month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month_of_era = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * month_of_era + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        let timestamp =
+            days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        timestamp.max(0) as u64
+    }
+}
+
+pub struct Rtc<'a> {
+    sys: &'a Sys,
+}
+
+impl<'a> Rtc<'a> {
+    pub fn new(sys: &'a Sys, pfic: &Pfic, clock32ksrc: Clock32KSrc) -> Self {
+        set_clock32ksrc(sys, clock32ksrc);
+
+        pfic.enable(CoreInterrupt::RTC, None);
+
+        Self { sys }
+    }
+
+    // returns the days since the epoch and the cycles since midnight
+    fn counters(&self) -> (u32, u32) {
+        loop {
+            let days = self.sys.rtc_cnt_day().read().bits() & DAY_MASK;
+            let cycles = self.cycles();
+            if days == self.sys.rtc_cnt_day().read().bits() & DAY_MASK && cycles == self.cycles() {
+                return (days, cycles);
+            }
+        }
+    }
+
+    fn cycles(&self) -> u32 {
+        // SAFETY: RTC_CNT_32K and RTC_CNT_2S form a single 32 bit counter
+        unsafe { (self.sys.rtc_cnt_32k().as_ptr() as *const u32).read_volatile() }
+    }
+
+    pub fn unix_timestamp(&self) -> u64 {
+        let (days, cycles) = self.counters();
+        (EPOCH_DAYS + days) as u64 * 86400 + (cycles / CYCLES_PER_SECOND) as u64
+    }
+
+    /// Timestamps outside of 2020-01-01 to 2064-11-08, the range of the day counter, are clamped.
+    pub fn set_unix_timestamp(&mut self, timestamp: u64) {
+        let timestamp = timestamp.clamp(
+            EPOCH_DAYS as u64 * 86400,
+            (EPOCH_DAYS + DAY_MASK) as u64 * 86400 + 86399,
+        );
+        let days = (timestamp / 86400) as u32 - EPOCH_DAYS;
+        let cycles = (timestamp % 86400) as u32 * CYCLES_PER_SECOND;
+
+        // the counters are loaded from the trigger register
+        with_safe_access_mode(|| {
+            self.sys.rtc_trig().write(|w| unsafe { w.bits(days) });
+            self.sys
+                .rtc_mode_ctrl()
+                .modify(|_, w| w.rtc_load_hi().set_bit());
+        });
+        while self.sys.rtc_cnt_day().read().bits() & DAY_MASK != days {}
+        with_safe_access_mode(|| {
+            self.sys.rtc_trig().write(|w| unsafe { w.bits(cycles) });
+            self.sys
+                .rtc_mode_ctrl()
+                .modify(|_, w| w.rtc_load_lo().set_bit());
+        });
+    }
+
+    pub fn datetime(&self) -> DateTime {
+        DateTime::from_unix_timestamp(self.unix_timestamp())
+    }
+
+    pub fn set_datetime(&mut self, datetime: &DateTime) {
+        self.set_unix_timestamp(datetime.to_unix_timestamp());
+    }
+
+    /// Triggers the alarm once, after at most a day.
+    pub fn set_alarm(&mut self, after: Duration) {
+        let after = (after.as_ticks().saturating_mul(CYCLES_PER_SECOND as u64) / TICK_HZ)
+            .min(CYCLES_PER_DAY as u64 - 1);
+        let trig = ((self.cycles() as u64 + after) % CYCLES_PER_DAY as u64) as u32;
+        self.sys
+            .rtc_flag_ctrl()
+            .write(|w| w.rtc_trig_clr().set_bit());
+        ALARMED.store(false, Ordering::Relaxed);
+        with_safe_access_mode(|| {
+            self.sys.rtc_trig().write(|w| unsafe { w.bits(trig) });
+            self.sys
+                .rtc_mode_ctrl()
+                .modify(|_, w| w.rtc_trig_en().set_bit());
+        });
+    }
+
+    pub fn cancel_alarm(&mut self) {
+        with_safe_access_mode(|| {
+            self.sys
+                .rtc_mode_ctrl()
+                .modify(|_, w| w.rtc_trig_en().clear_bit());
+        });
+        self.sys
+            .rtc_flag_ctrl()
+            .write(|w| w.rtc_trig_clr().set_bit());
+        ALARMED.store(false, Ordering::Relaxed);
+    }
+
+    /// Waits for the alarm, which is disabled afterwards.
+    pub async fn wait_alarm(&mut self) {
+        poll_fn(|cx| {
+            ALARM_WAKER.register(cx.waker());
+            if ALARMED.load(Ordering::Relaxed) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        self.cancel_alarm();
+    }
+
+    pub fn start_periodic(&mut self, period: Period) {
+        self.sys
+            .rtc_flag_ctrl()
+            .write(|w| w.rtc_tmr_clr().set_bit());
+        TICKED.store(false, Ordering::Relaxed);
+        with_safe_access_mode(|| {
+            self.sys.rtc_mode_ctrl().modify(|_, w| unsafe {
+                w.rtc_tmr_mode().bits(period as u8).rtc_tmr_en().set_bit()
+            });
+        });
+    }
+
+    pub fn stop_periodic(&mut self) {
+        with_safe_access_mode(|| {
+            self.sys
+                .rtc_mode_ctrl()
+                .modify(|_, w| w.rtc_tmr_en().clear_bit());
+        });
+        self.sys
+            .rtc_flag_ctrl()
+            .write(|w| w.rtc_tmr_clr().set_bit());
+        TICKED.store(false, Ordering::Relaxed);
+    }
+
+    pub async fn wait_period(&mut self) {
+        poll_fn(|cx| {
+            PERIOD_WAKER.register(cx.waker());
+            if TICKED.swap(false, Ordering::Relaxed) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::RTC)]
+fn rtc() {
+    let sys = unsafe { Sys::steal() };
+
+    // will be handled later
+    let flags = sys.rtc_flag_ctrl().read();
+    if flags.rtc_trig_flag().bit() {
+        sys.rtc_flag_ctrl().write(|w| w.rtc_trig_clr().set_bit());
+        ALARMED.store(true, Ordering::Relaxed);
+        ALARM_WAKER.wake();
+    }
+    if flags.rtc_tmr_flag().bit() {
+        sys.rtc_flag_ctrl().write(|w| w.rtc_tmr_clr().set_bit());
+        TICKED.store(true, Ordering::Relaxed);
+        PERIOD_WAKER.wake();
+    }
+}
@@ -0,0 +1,436 @@
+use crate::{
+    gpio::{
+        gpioa::{PA0, PA1, PA2},
+        Floating, Input, Output,
+    },
+    interrupt::CoreInterrupt,
+    pfic::PficExt,
+    raw::spi0::RegisterBlock,
+    remap::{Remap, Spi0Pins, Spi0SlavePins},
+    sys::SysExt,
+    Pfic, Spi0, Spi1, Sys,
+};
+use core::{convert::Infallible, future::poll_fn, task::Poll};
+use embassy_sync::waitqueue::AtomicWaker;
+
+const FIFO_SIZE: u8 = 8;
+const DMA_MAX_LEN: usize = 4095;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// CPOL = 0, CPHA = 0
+    Mode0,
+    /// CPOL = 1, CPHA = 1
+    Mode3,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+pub struct Config {
+    /// Rounded down to the next possible frequency, 0 gives the lowest one.
+    pub frequency: u32,
+    pub mode: Mode,
+    pub bit_order: BitOrder,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frequency: 1_000_000,
+            mode: Mode::Mode0,
+            bit_order: BitOrder::MsbFirst,
+        }
+    }
+}
+
+pub struct Spi1Pins {
+    pub sck: PA0<Output>,
+    pub mosi: PA1<Output>,
+    pub miso: PA2<Input<Floating>>,
+}
+
+impl Remap for Spi1Pins {
+    // SPI1 can't be remapped
+    fn remap(&self) {}
+}
+
+pub struct Spi<T: Instance> {
+    raw: T,
+    pins: T::Pins,
+}
+
+impl<T: Instance> Spi<T> {
+    pub fn new(raw: T, pins: T::Pins, sys: &Sys, pfic: &Pfic, config: Config) -> Self {
+        pins.remap();
+
+        let regs = T::regs();
+        let clock_div = sys.fsys().div_ceil(config.frequency.max(1)).clamp(2, 255);
+        regs.clock_div()
+            .write(|w| unsafe { w.clock_div().bits(clock_div as u8) });
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.ctrl_mod().write(|w| {
+            w.mosi_oe()
+                .set_bit()
+                .sck_oe()
+                .set_bit()
+                .mst_sck_mod()
+                .bit(config.mode == Mode::Mode3)
+        });
+        regs.ctrl_cfg().write(|w| {
+            w.auto_if()
+                .set_bit()
+                .bit_order()
+                .bit(config.bit_order == BitOrder::LsbFirst)
+        });
+
+        if let Some(interrupt) = T::INTERRUPT {
+            pfic.enable(interrupt, None);
+        }
+
+        Self { raw, pins }
+    }
+
+    pub fn free(self) -> (T, T::Pins) {
+        T::regs().ctrl_mod().write(|w| w.all_clear().set_bit());
+        (self.raw, self.pins)
+    }
+
+    fn blocking_exchange(&mut self, byte: u8) -> u8 {
+        let regs = T::regs();
+
+        regs.buffer().write(|w| unsafe { w.bits(byte) });
+        while regs.int_flag().read().free().bit_is_clear() {}
+        regs.buffer().read().bits()
+    }
+
+    pub fn blocking_read(&mut self, words: &mut [u8]) {
+        let regs = T::regs();
+
+        for chunk in words.chunks_mut(DMA_MAX_LEN) {
+            regs.ctrl_mod().modify(|_, w| w.fifo_dir().set_bit());
+            regs.total_cnt()
+                .write(|w| unsafe { w.bits(chunk.len() as u16) });
+            for word in chunk {
+                while regs.fifo_count().read().bits() == 0 {}
+                *word = regs.fifo().read().bits();
+            }
+        }
+    }
+
+    pub fn blocking_write(&mut self, words: &[u8]) {
+        let regs = T::regs();
+
+        for chunk in words.chunks(DMA_MAX_LEN) {
+            regs.ctrl_mod().modify(|_, w| w.fifo_dir().clear_bit());
+            regs.total_cnt()
+                .write(|w| unsafe { w.bits(chunk.len() as u16) });
+            for &word in chunk {
+                while regs.fifo_count().read().bits() >= FIFO_SIZE {}
+                regs.fifo().write(|w| unsafe { w.bits(word) });
+            }
+            self.blocking_flush();
+        }
+    }
+
+    pub fn blocking_transfer(&mut self, read: &mut [u8], write: &[u8]) {
+        T::regs().ctrl_mod().modify(|_, w| w.fifo_dir().clear_bit());
+        for i in 0..read.len().max(write.len()) {
+            let byte = self.blocking_exchange(write.get(i).copied().unwrap_or(0));
+            if let Some(word) = read.get_mut(i) {
+                *word = byte;
+            }
+        }
+    }
+
+    pub fn blocking_transfer_in_place(&mut self, words: &mut [u8]) {
+        T::regs().ctrl_mod().modify(|_, w| w.fifo_dir().clear_bit());
+        for word in words {
+            *word = self.blocking_exchange(*word);
+        }
+    }
+
+    pub fn blocking_flush(&mut self) {
+        let regs = T::regs();
+
+        while regs.total_cnt().read().bits() != 0 {}
+        while regs.int_flag().read().free().bit_is_clear() {}
+    }
+
+    pub async fn read(&mut self, words: &mut [u8]) {
+        if !T::DMA {
+            return self.blocking_read(words);
+        }
+
+        for chunk in words.chunks_mut(DMA_MAX_LEN) {
+            dma(T::regs(), chunk.as_ptr(), chunk.len(), true).await;
+        }
+    }
+
+    pub async fn write(&mut self, words: &[u8]) {
+        // DMA can only access RAM
+        if !T::DMA || (words.as_ptr() as usize) < 0x20000000 {
+            return self.blocking_write(words);
+        }
+
+        for chunk in words.chunks(DMA_MAX_LEN) {
+            dma(T::regs(), chunk.as_ptr(), chunk.len(), false).await;
+        }
+    }
+}
+
+// full-duplex transfers are only possible byte by byte, DMA is only used for reads and writes
+async fn dma(regs: &RegisterBlock, ptr: *const u8, len: usize, read: bool) {
+    regs.ctrl_mod().modify(|_, w| w.fifo_dir().bit(read));
+    regs.dma_beg()
+        .write(|w| unsafe { w.dma_beg().bits(ptr as u16) });
+    regs.dma_end()
+        .write(|w| unsafe { w.dma_end().bits(ptr.add(len) as u16) });
+    regs.total_cnt().write(|w| unsafe { w.bits(len as u16) });
+    regs.int_flag()
+        .write(|w| w.if_cnt_end().set_bit().if_dma_end().set_bit());
+    regs.inter_en().modify(|_, w| w.ie_cnt_end().set_bit());
+    regs.ctrl_cfg().modify(|_, w| w.dma_enable().set_bit());
+    let _transfer = Transfer { regs };
+
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+        if regs.int_flag().read().if_cnt_end().bit() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+}
+
+// the DMA is stopped once the transfer is dropped, so that it doesn't outlive the buffer
+struct Transfer<'a> {
+    regs: &'a RegisterBlock,
+}
+
+impl Drop for Transfer<'_> {
+    fn drop(&mut self) {
+        self.regs
+            .ctrl_cfg()
+            .modify(|_, w| w.dma_enable().clear_bit());
+        self.regs
+            .inter_en()
+            .modify(|_, w| w.ie_cnt_end().clear_bit());
+
+        // an aborted slave transfer leaves its count and FIFO behind, which would otherwise be
+        // shifted with the next command
+        if self.regs.ctrl_mod().read().mode_slave().bit()
+            && self.regs.int_flag().read().if_cnt_end().bit_is_clear()
+        {
+            self.regs.ctrl_mod().modify(|_, w| w.all_clear().set_bit());
+            self.regs
+                .ctrl_mod()
+                .modify(|_, w| w.all_clear().clear_bit());
+        }
+    }
+}
+
+impl<T: Instance> hal::spi::ErrorType for Spi<T> {
+    type Error = Infallible;
+}
+
+impl<T: Instance> hal::spi::SpiBus for Spi<T> {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.blocking_read(words);
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.blocking_write(words);
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        self.blocking_transfer(read, write);
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.blocking_transfer_in_place(words);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.blocking_flush();
+        Ok(())
+    }
+}
+
+impl<T: Instance> embedded_hal_async::spi::SpiBus for Spi<T> {
+    async fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        Spi::read(self, words).await;
+        Ok(())
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        Spi::write(self, words).await;
+        Ok(())
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        self.blocking_transfer(read, write);
+        Ok(())
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.blocking_transfer_in_place(words);
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.blocking_flush();
+        Ok(())
+    }
+}
+
+pub struct SpiSlave {
+    raw: Spi0,
+    pins: Spi0SlavePins,
+}
+
+impl SpiSlave {
+    // the clock mode is detected by the hardware
+    pub fn new(raw: Spi0, pins: Spi0SlavePins, pfic: &Pfic, command_mode: bool) -> Self {
+        pins.remap();
+
+        raw.ctrl_mod().write(|w| w.all_clear().set_bit());
+        raw.ctrl_mod().write(|w| {
+            w.miso_oe()
+                .set_bit()
+                .mode_slave()
+                .set_bit()
+                .slv_cmd_mod()
+                .bit(command_mode)
+        });
+        raw.ctrl_cfg().write(|w| w.auto_if().set_bit());
+
+        pfic.enable(CoreInterrupt::SPI0, None);
+
+        Self { raw, pins }
+    }
+
+    pub fn free(self) -> (Spi0, Spi0SlavePins) {
+        self.raw.ctrl_mod().write(|w| w.all_clear().set_bit());
+        (self.raw, self.pins)
+    }
+
+    /// Byte sent to the host while the command is received.
+    pub fn set_preload(&mut self, byte: u8) {
+        self.raw
+            .slave_pre()
+            .write(|w| unsafe { w.slave_pre().bits(byte) });
+    }
+
+    pub async fn command(&mut self) -> u8 {
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if self.raw.int_flag().read().if_fst_byte().bit() {
+                let command = self.raw.buffer().read().bits();
+                self.raw.int_flag().write(|w| w.if_fst_byte().set_bit());
+                Poll::Ready(command)
+            } else {
+                self.raw.inter_en().modify(|_, w| w.ie_fst_byte().set_bit());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    pub async fn read(&mut self, words: &mut [u8]) {
+        for chunk in words.chunks_mut(DMA_MAX_LEN) {
+            dma(&self.raw, chunk.as_ptr(), chunk.len(), true).await;
+        }
+    }
+
+    /// The bytes are pushed as the host clocks them out, so this blocks until the host has read
+    /// all of them.
+    pub fn blocking_write(&mut self, words: &[u8]) {
+        for chunk in words.chunks(DMA_MAX_LEN) {
+            self.raw.ctrl_mod().modify(|_, w| w.fifo_dir().clear_bit());
+            self.raw
+                .total_cnt()
+                .write(|w| unsafe { w.bits(chunk.len() as u16) });
+            for &word in chunk {
+                while self.raw.fifo_count().read().bits() >= FIFO_SIZE {}
+                self.raw.fifo().write(|w| unsafe { w.bits(word) });
+            }
+            while self.raw.total_cnt().read().bits() != 0 {}
+        }
+    }
+
+    pub async fn write(&mut self, words: &[u8]) {
+        // DMA can only access RAM
+        if (words.as_ptr() as usize) < 0x20000000 {
+            return self.blocking_write(words);
+        }
+
+        for chunk in words.chunks(DMA_MAX_LEN) {
+            dma(&self.raw, chunk.as_ptr(), chunk.len(), false).await;
+        }
+    }
+}
+
+mod sealed {
+    use crate::{interrupt::CoreInterrupt, raw::spi0::RegisterBlock, remap::Remap};
+
+    // SPI1 has the same register layout as SPI0, only without DMA
+    pub trait Instance {
+        const INTERRUPT: Option<CoreInterrupt>;
+        const DMA: bool;
+
+        type Pins: Remap;
+
+        fn regs() -> &'static RegisterBlock;
+    }
+}
+pub use sealed::Instance;
+
+impl Instance for Spi0 {
+    const INTERRUPT: Option<CoreInterrupt> = Some(CoreInterrupt::SPI0);
+    const DMA: bool = true;
+
+    type Pins = Spi0Pins<Output, Output, Input<Floating>>;
+
+    fn regs() -> &'static RegisterBlock {
+        unsafe { &*Spi0::ptr() }
+    }
+}
+
+impl Instance for Spi1 {
+    const INTERRUPT: Option<CoreInterrupt> = None;
+    const DMA: bool = false;
+
+    type Pins = Spi1Pins;
+
+    fn regs() -> &'static RegisterBlock {
+        unsafe { &*(Spi1::ptr() as *const RegisterBlock) }
+    }
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::SPI0)]
+fn spi0() {
+    let spi = unsafe { Spi0::steal() };
+    let inter_en = spi.inter_en().read();
+    let int_flag = spi.int_flag().read();
+    if inter_en.ie_cnt_end().bit() && int_flag.if_cnt_end().bit() {
+        // will be handled later
+        spi.inter_en().modify(|_, w| w.ie_cnt_end().clear_bit());
+        WAKER.wake();
+    }
+    if inter_en.ie_fst_byte().bit() && int_flag.if_fst_byte().bit() {
+        // will be handled later
+        spi.inter_en().modify(|_, w| w.ie_fst_byte().clear_bit());
+        WAKER.wake();
+    }
+}
@@ -1,115 +1,26 @@
-use crate::Sys;
-use riscv::asm::{delay, nop};
+use crate::{osc::calibrate_lsi, Pfic, Sys, Usb};
+use riscv::asm::{delay, nop, wfi};
 
 pub trait SysExt {
     fn set(self, config: Config) -> Self;
     fn fsys(&self) -> u32;
+
+    fn reset_reason(&self) -> ResetReason;
+    /// The value is kept during all resets except for power-on.
+    fn reset_keep(&self) -> u8;
+    fn set_reset_keep(&self, value: u8);
 }
 
 impl SysExt for Sys {
     fn set(self, config: Config) -> Self {
-        match config.clock32ksrc {
-            Clock32KSrc::LSE => {
-                // power-up external low speed oscillator
-                with_safe_access_mode(|| {
-                    self.ck32k_config()
-                        .modify(|_, w| w.clk_xt32k_pon().set_bit());
-                });
-                delay(self.fsys() / 10 / 4);
-                // ... and use it as 32k clock source
-                with_safe_access_mode(|| {
-                    self.ck32k_config()
-                        .modify(|_, w| w.clk_osc32k_xt().set_bit());
-                });
-                delay(self.fsys() / 1000);
-            }
-            Clock32KSrc::LSI => {
-                // power-up internal low speed oscillator and use it as 32k clock source
-                with_safe_access_mode(|| {
-                    self.ck32k_config().modify(|_, w| {
-                        w.clk_osc32k_xt()
-                            .clear_bit()
-                            .clk_int32k_pon()
-                            .set_bit()
-                            .clk_xt32k_pon()
-                            .clear_bit()
-                    });
-                });
-            }
-        }
-
-        with_safe_access_mode(|| {
-            self.pll_config()
-                .modify(|r, w| unsafe { w.pll_cfg_dat().bits(r.pll_cfg_dat().bits() & !(1 << 5)) });
-        });
-        match config.clocksyssrc {
-            ClockSysSrc::Clock32K => {
-                // use 32k clock as system clock
-                with_safe_access_mode(|| {
-                    self.clk_sys_cfg()
-                        .modify(|_, w| unsafe { w.clk_sys_mod().bits(0b11) });
-                });
-            }
-            ClockSysSrc::HSE(div) => {
-                // power-up external high speed oscillator
-                if self.hfck_pwr_ctrl().read().clk_xt32m_pon().bit_is_clear() {
-                    with_safe_access_mode(|| {
-                        self.hfck_pwr_ctrl()
-                            .modify(|_, w| w.clk_xt32m_pon().set_bit());
-                    });
-                    delay(2400);
-                }
-                // ... and use it as system clock source
-                with_safe_access_mode(|| {
-                    self.clk_sys_cfg().write(|w| unsafe {
-                        w.clk_sys_mod().bits(0b00).clk_pll_div().bits(div & 0x1F)
-                    });
-                    nop();
-                    nop();
-                    nop();
-                    nop();
-                });
-                nop();
-                nop();
-
-                with_safe_access_mode(|| {
-                    self.flash_cfg().write(|w| unsafe { w.bits(0x51) });
-                });
-            }
-            ClockSysSrc::PLL(div) => {
-                // power-up pll
-                if self.hfck_pwr_ctrl().read().clk_pll_pon().bit_is_clear() {
-                    with_safe_access_mode(|| {
-                        self.hfck_pwr_ctrl()
-                            .modify(|_, w| w.clk_pll_pon().set_bit());
-                    });
-                    delay(4000);
-                }
-                // ... and use it as system clock source
-                with_safe_access_mode(|| {
-                    self.clk_sys_cfg().write(|w| unsafe {
-                        w.clk_sys_mod().bits(0b01).clk_pll_div().bits(div & 0x1F)
-                    });
-                    nop();
-                    nop();
-                    nop();
-                    nop();
-                });
+        let calibrate = config.clock32ksrc == Clock32KSrc::LSI
+            && !matches!(config.clocksyssrc, ClockSysSrc::Clock32K);
+        set_clock32ksrc(&self, config.clock32ksrc);
 
-                if div == 6 {
-                    with_safe_access_mode(|| {
-                        self.flash_cfg().write(|w| unsafe { w.bits(0x02) });
-                    });
-                } else {
-                    with_safe_access_mode(|| {
-                        self.flash_cfg().write(|w| unsafe { w.bits(0x52) });
-                    });
-                }
-            }
+        set_clocksyssrc(&self, config.clocksyssrc);
+        if calibrate {
+            calibrate_lsi(&self);
         }
-        with_safe_access_mode(|| {
-            self.pll_config().modify(|_, w| w.flash_io_mod().set_bit());
-        });
 
         self
     }
@@ -123,8 +34,27 @@ impl SysExt for Sys {
             _ => 32_000,
         }
     }
+
+    fn reset_reason(&self) -> ResetReason {
+        match self.reset_status().read().reset_flag().bits() {
+            0b001 => ResetReason::PowerOn,
+            0b010 | 0b110 => ResetReason::Watchdog,
+            0b011 | 0b111 => ResetReason::ExternalPin,
+            0b101 => ResetReason::WakeFromShutdown,
+            _ => ResetReason::Software,
+        }
+    }
+
+    fn reset_keep(&self) -> u8 {
+        self.glob_reset_keep().read().bits()
+    }
+
+    fn set_reset_keep(&self, value: u8) {
+        self.glob_reset_keep().write(|w| unsafe { w.bits(value) });
+    }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Clock32KSrc {
     LSE,
     LSI,
@@ -136,11 +66,167 @@ pub enum ClockSysSrc {
     PLL(u8),
 }
 
+// resets while waking up from shutdown are reported with their cause
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ResetReason {
+    PowerOn,
+    Software,
+    Watchdog,
+    ExternalPin,
+    WakeFromShutdown,
+}
+
 pub struct Config {
     pub clock32ksrc: Clock32KSrc,
     pub clocksyssrc: ClockSysSrc,
 }
 
+pub(crate) fn set_clock32ksrc(sys: &Sys, clock32ksrc: Clock32KSrc) {
+    match clock32ksrc {
+        Clock32KSrc::LSE => {
+            // power-up external low speed oscillator
+            with_safe_access_mode(|| {
+                sys.ck32k_config()
+                    .modify(|_, w| w.clk_xt32k_pon().set_bit());
+            });
+            delay(sys.fsys() / 10 / 4);
+            // ... and use it as 32k clock source
+            with_safe_access_mode(|| {
+                sys.ck32k_config()
+                    .modify(|_, w| w.clk_osc32k_xt().set_bit());
+            });
+            delay(sys.fsys() / 1000);
+        }
+        Clock32KSrc::LSI => {
+            // power-up internal low speed oscillator and use it as 32k clock source
+            with_safe_access_mode(|| {
+                sys.ck32k_config().modify(|_, w| {
+                    w.clk_osc32k_xt()
+                        .clear_bit()
+                        .clk_int32k_pon()
+                        .set_bit()
+                        .clk_xt32k_pon()
+                        .clear_bit()
+                });
+            });
+        }
+    }
+}
+
+pub(crate) fn set_clocksyssrc(sys: &Sys, clocksyssrc: ClockSysSrc) {
+    with_safe_access_mode(|| {
+        sys.pll_config()
+            .modify(|r, w| unsafe { w.pll_cfg_dat().bits(r.pll_cfg_dat().bits() & !(1 << 5)) });
+    });
+    match clocksyssrc {
+        ClockSysSrc::Clock32K => {
+            // use 32k clock as system clock
+            with_safe_access_mode(|| {
+                sys.clk_sys_cfg()
+                    .modify(|_, w| unsafe { w.clk_sys_mod().bits(0b11) });
+            });
+        }
+        ClockSysSrc::HSE(div) => {
+            // power-up external high speed oscillator
+            if sys.hfck_pwr_ctrl().read().clk_xt32m_pon().bit_is_clear() {
+                with_safe_access_mode(|| {
+                    sys.hfck_pwr_ctrl()
+                        .modify(|_, w| w.clk_xt32m_pon().set_bit());
+                });
+                delay(2400);
+            }
+            // ... and use it as system clock source
+            with_safe_access_mode(|| {
+                sys.clk_sys_cfg().write(|w| unsafe {
+                    w.clk_sys_mod().bits(0b00).clk_pll_div().bits(div & 0x1F)
+                });
+                nop();
+                nop();
+                nop();
+                nop();
+            });
+            nop();
+            nop();
+
+            with_safe_access_mode(|| {
+                sys.flash_cfg().write(|w| unsafe { w.bits(0x51) });
+            });
+        }
+        ClockSysSrc::PLL(div) => {
+            // power-up pll
+            if sys.hfck_pwr_ctrl().read().clk_pll_pon().bit_is_clear() {
+                with_safe_access_mode(|| {
+                    sys.hfck_pwr_ctrl().modify(|_, w| w.clk_pll_pon().set_bit());
+                });
+                delay(4000);
+            }
+            // ... and use it as system clock source
+            with_safe_access_mode(|| {
+                sys.clk_sys_cfg().write(|w| unsafe {
+                    w.clk_sys_mod().bits(0b01).clk_pll_div().bits(div & 0x1F)
+                });
+                nop();
+                nop();
+                nop();
+                nop();
+            });
+
+            if div == 6 {
+                with_safe_access_mode(|| {
+                    sys.flash_cfg().write(|w| unsafe { w.bits(0x02) });
+                });
+            } else {
+                with_safe_access_mode(|| {
+                    sys.flash_cfg().write(|w| unsafe { w.bits(0x52) });
+                });
+            }
+        }
+    }
+    with_safe_access_mode(|| {
+        sys.pll_config().modify(|_, w| w.flash_io_mod().set_bit());
+    });
+}
+
+pub fn reset() -> ! {
+    with_safe_access_mode(|| {
+        unsafe { Sys::steal() }
+            .rst_wdog_ctrl()
+            .modify(|_, w| w.software_reset().set_bit());
+    });
+    loop {
+        wfi();
+    }
+}
+
+pub fn reset_to_isp_bootloader() -> ! {
+    critical_section::with(|_| {
+        let sys = unsafe { Sys::steal() };
+
+        // disconnect from the host, so that the loader is enumerated again
+        let usb = unsafe { Usb::steal() };
+        usb.ctrl()
+            .write(|w| w.uc_reset_sie().set_bit().uc_clr_all().set_bit());
+        usb.udev_ctrl().reset();
+        sys.pin_analog_ie()
+            .modify(|_, w| w.pin_usb_ie().clear_bit().pin_usb_dp_pu().clear_bit());
+        delay(sys.fsys() / 100);
+
+        // the loader expects the state after a reset: no interrupts enabled or pending, the
+        // system clock at HSE / 5 and no trap vector of the application
+        let pfic = unsafe { Pfic::steal() };
+        pfic.irer1().write(|w| unsafe { w.bits(u32::MAX) });
+        pfic.irer2().write(|w| unsafe { w.bits(u32::MAX) });
+        pfic.iprr1().write(|w| unsafe { w.bits(u32::MAX) });
+        pfic.iprr2().write(|w| unsafe { w.bits(u32::MAX) });
+        set_clocksyssrc(&sys, ClockSysSrc::HSE(5));
+        unsafe { core::arch::asm!("csrw mtvec, zero") };
+
+        // the BootLoader area of the code flash starts at 0x0007_8000, as listed in the memory
+        // map of the CH583 datasheet
+        unsafe { core::arch::asm!("jr {}", in(reg) 0x0007_8000usize, options(noreturn)) }
+    })
+}
+
 pub fn with_safe_access_mode<R>(f: impl FnOnce() -> R) -> R {
     critical_section::with(|_| {
         unsafe {
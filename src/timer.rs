@@ -0,0 +1,606 @@
+use crate::{
+    gpio::{Input, Output},
+    interrupt::CoreInterrupt,
+    pfic::PficExt,
+    pwm::Polarity,
+    raw::{tmr0::RegisterBlock, tmr1},
+    remap::{Remap, Tmr0Pin, Tmr1Pin, Tmr2Pin, Tmr3Pin},
+    sys::SysExt,
+    Pfic, Sys, Tmr0, Tmr1, Tmr2, Tmr3,
+};
+use core::{
+    convert::Infallible,
+    future::poll_fn,
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+use embassy_sync::waitqueue::AtomicWaker;
+use embassy_time::{Duration, TICK_HZ};
+
+// the end count is only 26 bits wide
+const MAX_TICKS: u32 = (1 << 26) - 1;
+
+const CYC_END: u8 = 1 << 0;
+const DATA_ACT: u8 = 1 << 1;
+const DMA_END: u8 = 1 << 3;
+const FIFO_OV: u8 = 1 << 4;
+
+// duty cycles in flash are copied to RAM in chunks of this length
+const CHUNK_LEN: usize = 32;
+
+// captured widths are 25 bits wide, followed by the level when capturing on any edge
+const CAPTURE_WIDTH: u32 = (1 << 25) - 1;
+const CAPTURE_HIGH: u32 = 1 << 25;
+
+static WAKERS: [AtomicWaker; 4] = [const { AtomicWaker::new() }; 4];
+// one-shot timers are stopped by the interrupt, even if nobody waits for them
+static ONE_SHOT: [AtomicBool; 4] = [const { AtomicBool::new(false) }; 4];
+
+pub struct Timer<T: Instance> {
+    raw: T,
+    fsys: u32,
+}
+
+impl<T: Instance> Timer<T> {
+    pub fn new(raw: T, sys: &Sys, pfic: &Pfic) -> Self {
+        T::regs().ctrl_mod().write(|w| w.all_clear().set_bit());
+
+        pfic.enable(T::INTERRUPT, None);
+
+        Self {
+            raw,
+            fsys: sys.fsys(),
+        }
+    }
+
+    pub fn free(self) -> T {
+        let regs = T::regs();
+        ONE_SHOT[T::INDEX].store(false, Ordering::Relaxed);
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.inter_en().reset();
+        self.raw
+    }
+
+    fn start(&mut self, ticks: u32, one_shot: bool) {
+        let regs = T::regs();
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.cnt_end().write(|w| unsafe { w.cnt_end().bits(ticks) });
+        regs.int_flag().write(|w| w.if_cyc_end().set_bit());
+        ONE_SHOT[T::INDEX].store(one_shot, Ordering::Relaxed);
+        if one_shot {
+            regs.inter_en().modify(|_, w| w.ie_cyc_end().set_bit());
+        }
+        regs.ctrl_mod().write(|w| w.count_en().set_bit());
+    }
+
+    pub fn start_periodic(&mut self, period: Duration) {
+        self.start(ticks(self.fsys, period), false);
+    }
+
+    /// The timer is stopped at the end of the timeout.
+    pub fn start_one_shot(&mut self, timeout: Duration) {
+        self.start(ticks(self.fsys, timeout), true);
+    }
+
+    pub fn stop(&mut self) {
+        ONE_SHOT[T::INDEX].store(false, Ordering::Relaxed);
+        T::regs().ctrl_mod().write(|w| w.all_clear().set_bit());
+    }
+
+    /// Ticks since the start of the current period, at `fsys`.
+    pub fn count(&self) -> u32 {
+        T::regs().count().read().bits()
+    }
+
+    /// Waits for the end of the current period.
+    pub async fn wait_period(&mut self) {
+        wait_for::<T>(CYC_END).await;
+        T::regs().int_flag().write(|w| w.if_cyc_end().set_bit());
+    }
+}
+
+impl<T: Instance> embedded_hal_async::delay::DelayNs for Timer<T> {
+    async fn delay_ns(&mut self, ns: u32) {
+        let mut ticks = ns as u64 * self.fsys as u64 / 1_000_000_000;
+        while ticks > 0 {
+            let chunk = ticks.min(MAX_TICKS as u64) as u32;
+            ticks -= chunk as u64;
+            self.start(chunk.max(2), true);
+            self.wait_period().await;
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Repeat {
+    Once,
+    Times4,
+    Times8,
+    Times16,
+}
+
+pub struct PwmConfig {
+    pub frequency: u32,
+    pub polarity: Polarity,
+    /// Number of cycles each duty cycle is output for, before the next is taken from the FIFO
+    pub repeat: Repeat,
+}
+
+impl Default for PwmConfig {
+    fn default() -> Self {
+        Self {
+            frequency: 10_000,
+            polarity: Polarity::ActiveHigh,
+            repeat: Repeat::Once,
+        }
+    }
+}
+
+pub struct Pwm<T: Instance> {
+    raw: T,
+    pin: T::Pin<Output>,
+}
+
+impl<T: Instance> Pwm<T> {
+    pub fn new(raw: T, pin: T::Pin<Output>, sys: &Sys, pfic: &Pfic, config: PwmConfig) -> Self {
+        pin.remap();
+
+        let regs = T::regs();
+        let period = (sys.fsys() / config.frequency.max(1)).clamp(2, MAX_TICKS);
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.cnt_end()
+            .write(|w| unsafe { w.cnt_end().bits(period) });
+        write_fifo::<T>(0);
+        regs.ctrl_mod().write(|w| unsafe {
+            w.count_en()
+                .set_bit()
+                .out_en()
+                .set_bit()
+                .out_polar()
+                .bit(config.polarity == Polarity::ActiveLow)
+                .pwm_repeat()
+                .bits(config.repeat as u8)
+        });
+
+        pfic.enable(T::INTERRUPT, None);
+
+        Self { raw, pin }
+    }
+
+    pub fn free(self) -> (T, T::Pin<Output>) {
+        let regs = T::regs();
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.inter_en().reset();
+        (self.raw, self.pin)
+    }
+
+    /// Period in ticks at `fsys`, which is the maximum duty cycle.
+    pub fn period(&self) -> u32 {
+        T::regs().cnt_end().read().bits()
+    }
+
+    /// Duty cycle in ticks at `fsys`, with the full 26 bit resolution.
+    pub fn set_duty_cycle_ticks(&mut self, duty: u32) {
+        write_fifo::<T>(duty.min(self.period()));
+    }
+}
+
+impl<T: Instance> hal::pwm::ErrorType for Pwm<T> {
+    type Error = Infallible;
+}
+
+// duty cycles are scaled to the period, if it doesn't fit into 16 bits
+impl<T: Instance> hal::pwm::SetDutyCycle for Pwm<T> {
+    fn max_duty_cycle(&self) -> u16 {
+        self.period().min(u16::MAX as u32) as u16
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        let duty = duty as u64 * self.period() as u64 / self.max_duty_cycle() as u64;
+        self.set_duty_cycle_ticks(duty as u32);
+        Ok(())
+    }
+}
+
+impl<T: DmaInstance> Pwm<T> {
+    /// Outputs the duty cycles in ticks one after another, each for the configured number of
+    /// repeats.
+    pub async fn play(&mut self, duties: &[u32]) {
+        // an empty transfer never ends
+        if duties.is_empty() {
+            return;
+        }
+        // DMA can only access RAM
+        if in_ram(duties) {
+            return self.play_dma(duties).await;
+        }
+
+        let mut buffer = [0; CHUNK_LEN];
+        for chunk in duties.chunks(CHUNK_LEN) {
+            let buffer = &mut buffer[..chunk.len()];
+            buffer.copy_from_slice(chunk);
+            self.play_dma(buffer).await;
+        }
+    }
+
+    async fn play_dma(&mut self, duties: &[u32]) {
+        let _dma = Dma::<T>::start(duties.as_ptr(), duties.len(), false);
+        wait_for::<T>(DMA_END).await;
+        T::regs().int_flag().write(|w| w.if_dma_end().set_bit());
+    }
+
+    /// Outputs the duty cycles in ticks over and over again, until the returned transfer is
+    /// dropped. The duty cycles have to be in RAM.
+    pub fn play_circular<'a>(&'a mut self, duties: &'a [u32]) -> Result<Circular<'a, T>, Error> {
+        if !in_ram(duties) {
+            return Err(Error::NotInRam);
+        }
+
+        Ok(Circular {
+            _dma: (!duties.is_empty()).then(|| Dma::start(duties.as_ptr(), duties.len(), true)),
+            _pwm: PhantomData,
+        })
+    }
+}
+
+pub struct Circular<'a, T: DmaInstance> {
+    // nothing is output for no duty cycles
+    _dma: Option<Dma<T>>,
+    _pwm: PhantomData<&'a mut Pwm<T>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Edge {
+    Any = 0b01,
+    Falling = 0b10,
+    Rising = 0b11,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Measurement {
+    /// Ticks between two edges while the input was high, only captured on any edge
+    High(u32),
+    /// Ticks between two edges while the input was low, only captured on any edge
+    Low(u32),
+    /// Ticks between two falling or two rising edges
+    Period(u32),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Captures have been lost, as the FIFO wasn't read in time
+    Overflow,
+    /// No edge has been captured within the configured timeout
+    Timeout,
+    /// DMA can only read from RAM, not from flash
+    NotInRam,
+}
+
+pub struct CaptureConfig {
+    pub edge: Edge,
+    pub timeout: Duration,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            edge: Edge::Any,
+            timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+pub struct Capture<T: Instance, PULL> {
+    raw: T,
+    pin: T::Pin<Input<PULL>>,
+    edge: Edge,
+}
+
+impl<T: Instance, PULL> Capture<T, PULL> {
+    pub fn new(
+        raw: T,
+        pin: T::Pin<Input<PULL>>,
+        sys: &Sys,
+        pfic: &Pfic,
+        config: CaptureConfig,
+    ) -> Self {
+        pin.remap();
+
+        let regs = T::regs();
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.cnt_end()
+            .write(|w| unsafe { w.cnt_end().bits(ticks(sys.fsys(), config.timeout)) });
+        regs.int_flag().write(|w| unsafe { w.bits(0xFF) });
+        regs.ctrl_mod().write(|w| unsafe {
+            w.mode_in()
+                .set_bit()
+                .count_en()
+                .set_bit()
+                .cap_edge()
+                .bits(config.edge as u8)
+        });
+
+        pfic.enable(T::INTERRUPT, None);
+
+        Self {
+            raw,
+            pin,
+            edge: config.edge,
+        }
+    }
+
+    pub fn free(self) -> (T, T::Pin<Input<PULL>>) {
+        let regs = T::regs();
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.inter_en().reset();
+        (self.raw, self.pin)
+    }
+
+    /// Decodes a raw capture, as stored by DMA.
+    pub fn decode(&self, capture: u32) -> Measurement {
+        let width = capture & CAPTURE_WIDTH;
+        match self.edge {
+            Edge::Any if capture & CAPTURE_HIGH != 0 => Measurement::High(width),
+            Edge::Any => Measurement::Low(width),
+            Edge::Falling | Edge::Rising => Measurement::Period(width),
+        }
+    }
+
+    pub async fn read(&mut self) -> Result<Measurement, Error> {
+        let regs = T::regs();
+
+        loop {
+            // cleared first, so that no capture is missed between checking the FIFO and waiting
+            regs.int_flag().write(|w| w.if_data_act().set_bit());
+            let flags = regs.int_flag().read().bits();
+            if flags & FIFO_OV != 0 {
+                regs.int_flag().write(|w| w.if_fifo_ov().set_bit());
+                return Err(Error::Overflow);
+            }
+            if regs.fifo_count().read().bits() != 0 {
+                return Ok(self.decode(regs.fifo().read().bits()));
+            }
+            if flags & CYC_END != 0 {
+                regs.int_flag().write(|w| w.if_cyc_end().set_bit());
+                return Err(Error::Timeout);
+            }
+
+            wait_for::<T>(DATA_ACT | FIFO_OV | CYC_END).await;
+        }
+    }
+}
+
+impl<T: DmaInstance, PULL> Capture<T, PULL> {
+    /// Captures until the buffer is full, the raw captures can be decoded with
+    /// [`Capture::decode`]. Stops early if no edge is captured within the configured timeout.
+    pub async fn read_dma(&mut self, buffer: &mut [u32]) -> Result<(), Error> {
+        let regs = T::regs();
+
+        // an empty transfer never ends
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        // only time out on edges missed during the transfer
+        regs.int_flag().write(|w| w.if_cyc_end().set_bit());
+
+        let _dma = Dma::<T>::start(buffer.as_mut_ptr(), buffer.len(), false);
+        let flags = wait_for::<T>(DMA_END | FIFO_OV | CYC_END).await;
+        if flags & FIFO_OV != 0 {
+            regs.int_flag().write(|w| w.if_fifo_ov().set_bit());
+            return Err(Error::Overflow);
+        }
+        if flags & DMA_END != 0 {
+            regs.int_flag().write(|w| w.if_dma_end().set_bit());
+            return Ok(());
+        }
+        regs.int_flag().write(|w| w.if_cyc_end().set_bit());
+        Err(Error::Timeout)
+    }
+}
+
+pub struct Counter<T: Instance, PULL> {
+    raw: T,
+    pin: T::Pin<Input<PULL>>,
+    edge: Edge,
+}
+
+impl<T: Instance, PULL> Counter<T, PULL> {
+    pub fn new(raw: T, pin: T::Pin<Input<PULL>>, pfic: &Pfic, edge: Edge) -> Self {
+        pin.remap();
+
+        let counter = Self { raw, pin, edge };
+        counter.start(MAX_TICKS);
+
+        pfic.enable(T::INTERRUPT, None);
+
+        counter
+    }
+
+    pub fn free(self) -> (T, T::Pin<Input<PULL>>) {
+        let regs = T::regs();
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.inter_en().reset();
+        (self.raw, self.pin)
+    }
+
+    // the end count is 2 more than the number of edges
+    fn start(&self, end: u32) {
+        let regs = T::regs();
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+        regs.cnt_end().write(|w| unsafe { w.cnt_end().bits(end) });
+        regs.int_flag().write(|w| w.if_cyc_end().set_bit());
+        regs.ctrl_mod().write(|w| unsafe {
+            w.mode_in()
+                .set_bit()
+                .cap_count()
+                .set_bit()
+                .count_en()
+                .set_bit()
+                .cap_edge()
+                .bits(self.edge as u8)
+        });
+    }
+
+    /// Edges counted since the last reset.
+    pub fn count(&self) -> u32 {
+        T::regs().count().read().bits()
+    }
+
+    pub fn reset(&mut self) {
+        self.start(MAX_TICKS);
+    }
+
+    /// Resets the count and waits for the given number of edges, afterwards the count restarts
+    /// every `n` edges until the next reset.
+    pub async fn wait_for_count(&mut self, n: u32) {
+        self.start(n.saturating_add(2).min(MAX_TICKS));
+        wait_for::<T>(CYC_END).await;
+        T::regs().int_flag().write(|w| w.if_cyc_end().set_bit());
+    }
+}
+
+// the DMA is stopped once the transfer is dropped
+struct Dma<T: DmaInstance> {
+    _raw: PhantomData<T>,
+}
+
+impl<T: DmaInstance> Dma<T> {
+    // the memory has to be in RAM, writable if the DMA writes to it
+    fn start(ptr: *const u32, len: usize, circular: bool) -> Self {
+        let regs = T::dma_regs();
+        regs.int_flag().write(|w| w.if_dma_end().set_bit());
+        regs.dma_beg()
+            .write(|w| unsafe { w.dma_beg().bits(ptr as u16) });
+        regs.dma_end()
+            .write(|w| unsafe { w.dma_end().bits(ptr.wrapping_add(len) as u16) });
+        regs.ctrl_dma()
+            .write(|w| w.dma_enable().set_bit().dma_loop().bit(circular));
+
+        Self { _raw: PhantomData }
+    }
+}
+
+impl<T: DmaInstance> Drop for Dma<T> {
+    fn drop(&mut self) {
+        T::dma_regs().ctrl_dma().reset();
+    }
+}
+
+fn in_ram(words: &[u32]) -> bool {
+    words.as_ptr() as usize >= 0x20000000
+}
+
+fn ticks(fsys: u32, duration: Duration) -> u32 {
+    (duration.as_ticks() * fsys as u64 / TICK_HZ).clamp(2, MAX_TICKS as u64) as u32
+}
+
+fn write_fifo<T: Instance>(value: u32) {
+    // SAFETY: the FIFO is writable, even though it isn't described as such
+    unsafe { T::regs().fifo().as_ptr().write_volatile(value) };
+}
+
+// waits for any of the interrupt flags, which are left set
+async fn wait_for<T: Instance>(mask: u8) -> u8 {
+    let regs = T::regs();
+    poll_fn(|cx| {
+        WAKERS[T::INDEX].register(cx.waker());
+        let flags = regs.int_flag().read().bits() & mask;
+        if flags != 0 {
+            Poll::Ready(flags)
+        } else {
+            regs.inter_en()
+                .modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+mod sealed {
+    use crate::{
+        interrupt::CoreInterrupt,
+        raw::{tmr0::RegisterBlock, tmr1},
+        remap::Remap,
+    };
+
+    // TMR1..3 have the same register layout as TMR0, TMR1 and TMR2 with additional DMA registers
+    pub trait Instance {
+        const INTERRUPT: CoreInterrupt;
+        const INDEX: usize;
+
+        type Pin<MODE>: Remap;
+
+        fn regs() -> &'static RegisterBlock;
+    }
+
+    pub trait DmaInstance: Instance {
+        fn dma_regs() -> &'static tmr1::RegisterBlock;
+    }
+}
+pub use sealed::{DmaInstance, Instance};
+
+macro_rules! instance {
+    ($Tmr:ident, $Pin:ident, $INTERRUPT:ident, $index:expr) => {
+        impl Instance for $Tmr {
+            const INTERRUPT: CoreInterrupt = CoreInterrupt::$INTERRUPT;
+            const INDEX: usize = $index;
+
+            type Pin<MODE> = $Pin<MODE>;
+
+            fn regs() -> &'static RegisterBlock {
+                unsafe { &*($Tmr::ptr() as *const RegisterBlock) }
+            }
+        }
+    };
+}
+
+instance!(Tmr0, Tmr0Pin, TMR0, 0);
+instance!(Tmr1, Tmr1Pin, TMR1, 1);
+instance!(Tmr2, Tmr2Pin, TMR2, 2);
+instance!(Tmr3, Tmr3Pin, TMR3, 3);
+
+impl DmaInstance for Tmr1 {
+    fn dma_regs() -> &'static tmr1::RegisterBlock {
+        unsafe { &*Tmr1::ptr() }
+    }
+}
+
+impl DmaInstance for Tmr2 {
+    fn dma_regs() -> &'static tmr1::RegisterBlock {
+        unsafe { &*(Tmr2::ptr() as *const tmr1::RegisterBlock) }
+    }
+}
+
+fn on_interrupt<T: Instance>() {
+    let regs = T::regs();
+
+    // will be handled later
+    let flags = regs.int_flag().read().bits();
+    regs.inter_en()
+        .modify(|r, w| unsafe { w.bits(r.bits() & !flags) });
+    if flags & CYC_END != 0 && ONE_SHOT[T::INDEX].swap(false, Ordering::Relaxed) {
+        regs.ctrl_mod().write(|w| w.all_clear().set_bit());
+    }
+    WAKERS[T::INDEX].wake();
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::TMR0)]
+fn tmr0() {
+    on_interrupt::<Tmr0>();
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::TMR1)]
+fn tmr1() {
+    on_interrupt::<Tmr1>();
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::TMR2)]
+fn tmr2() {
+    on_interrupt::<Tmr2>();
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::TMR3)]
+fn tmr3() {
+    on_interrupt::<Tmr3>();
+}
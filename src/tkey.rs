@@ -0,0 +1,143 @@
+use crate::{
+    adc::{AdcChannel, Gain},
+    Adc, Sys,
+};
+use embassy_time::{Duration, Timer};
+
+pub mod filter;
+pub use filter::{Event, Filter};
+
+#[derive(Copy, Clone)]
+pub struct Config {
+    /// 0 to 31
+    pub charge: u8,
+    /// 0 to 7
+    pub discharge: u8,
+    pub filter: filter::Config,
+    pub interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            charge: 0x10,
+            discharge: 0,
+            filter: filter::Config::default(),
+            interval: Duration::from_millis(20),
+        }
+    }
+}
+
+/// The pins scanned as keys, either an array or a tuple of up to 14 channels.
+pub trait Keys<const N: usize> {
+    fn channels(&self) -> [u8; N];
+}
+
+impl<C: AdcChannel, const N: usize> Keys<N> for [C; N] {
+    fn channels(&self) -> [u8; N] {
+        core::array::from_fn(|i| self[i].channel())
+    }
+}
+
+macro_rules! keys {
+    ($N:literal: $($C:ident $i:tt),+) => {
+        impl<$($C: AdcChannel),+> Keys<$N> for ($($C,)+) {
+            fn channels(&self) -> [u8; $N] {
+                [$(self.$i.channel()),+]
+            }
+        }
+    };
+}
+
+keys!(1: A 0);
+keys!(2: A 0, B 1);
+keys!(3: A 0, B 1, C 2);
+keys!(4: A 0, B 1, C 2, D 3);
+keys!(5: A 0, B 1, C 2, D 3, E 4);
+keys!(6: A 0, B 1, C 2, D 3, E 4, F 5);
+keys!(7: A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+keys!(8: A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+keys!(9: A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+keys!(10: A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+keys!(11: A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+keys!(12: A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+keys!(13: A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11, M 12);
+keys!(14: A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11, M 12, N 13);
+
+pub struct TouchKey<'a, K: Keys<N>, const N: usize> {
+    raw: Adc,
+    sys: &'a Sys,
+    keys: K,
+    channels: [u8; N],
+    filters: [Filter; N],
+    config: Config,
+    next: usize,
+}
+
+impl<'a, K: Keys<N>, const N: usize> TouchKey<'a, K, N> {
+    pub fn new(raw: Adc, sys: &'a Sys, keys: K, config: Config) -> Self {
+        raw.cfg().write(|w| {
+            w.power_on()
+                .set_bit()
+                .buf_en()
+                .set_bit()
+                .pga_gain()
+                .variant(Gain::_1)
+        });
+        raw.convert().reset();
+        sys.tkey_cfg().write(|w| w.tkey_pwr_on().set_bit());
+
+        Self {
+            raw,
+            sys,
+            channels: keys.channels(),
+            keys,
+            filters: [Filter::new(); N],
+            config,
+            next: 0,
+        }
+    }
+
+    pub fn free(self) -> (Adc, K) {
+        self.sys.tkey_cfg().reset();
+        self.raw.cfg().reset();
+        (self.raw, self.keys)
+    }
+
+    pub fn filter(&self, key: usize) -> &Filter {
+        &self.filters[key]
+    }
+
+    pub fn read_one(&mut self, key: usize) -> u16 {
+        self.raw
+            .channel()
+            .write(|w| unsafe { w.ch_inx().bits(self.channels[key]) });
+        self.sys.tkey_count().write(|w| unsafe {
+            w.tkey_charg_cnt()
+                .bits(self.config.charge)
+                .tkey_disch_cnt()
+                .bits(self.config.discharge)
+        });
+        self.sys.tkey_convert().write(|w| w.tkey_start().set_bit());
+        while self.sys.tkey_convert().read().tkey_start().bit() {}
+        self.raw.data().read().bits()
+    }
+
+    /// Scans the keys every interval until one of them changes its state.
+    pub async fn next(&mut self) -> (usize, Event) {
+        loop {
+            while self.next < N {
+                let key = self.next;
+                self.next += 1;
+
+                let count = self.read_one(key);
+                if let Some(event) = self.filters[key].update(&self.config.filter, count) {
+                    return (key, event);
+                }
+            }
+            self.next = 0;
+
+            Timer::after(self.config.interval).await;
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// only depends on core, so that the tests can run on the host:
+// rustc --edition 2021 --test src/tkey/filter.rs -o filter && ./filter
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    Touch,
+    Release,
+}
+
+#[derive(Copy, Clone)]
+pub struct Config {
+    /// Decrease of the count below the baseline that is reported as touch.
+    pub threshold: u16,
+    /// Decrease of the threshold while touched.
+    pub hysteresis: u16,
+    /// Consecutive samples needed to change the state.
+    pub debounce: u8,
+    /// The baseline follows the count with a weight of 1/2^drift, at most 31.
+    pub drift: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            threshold: 100,
+            hysteresis: 30,
+            debounce: 3,
+            drift: 6,
+        }
+    }
+}
+
+// doesn't touch any registers, the counts are passed in by the driver
+#[derive(Copy, Clone, Default)]
+pub struct Filter {
+    // 8 fractional bits
+    baseline: Option<u32>,
+    touched: bool,
+    pending: u8,
+}
+
+impl Filter {
+    pub const fn new() -> Self {
+        Self {
+            baseline: None,
+            touched: false,
+            pending: 0,
+        }
+    }
+
+    pub fn is_touched(&self) -> bool {
+        self.touched
+    }
+
+    pub fn baseline(&self) -> Option<u16> {
+        self.baseline.map(|baseline| (baseline >> 8) as u16)
+    }
+
+    /// The first count is taken as the baseline, so the key must not be touched while starting.
+    pub fn update(&mut self, config: &Config, count: u16) -> Option<Event> {
+        let sample = (count as u32) << 8;
+        let baseline = *self.baseline.get_or_insert(sample);
+        // the count decreases with the capacitance of a finger
+        let delta = (baseline >> 8) as i32 - count as i32;
+
+        let touched = if self.touched {
+            delta > config.threshold as i32 - config.hysteresis as i32
+        } else {
+            delta >= config.threshold as i32
+        };
+
+        if touched == self.touched {
+            self.pending = 0;
+            // the baseline is kept while touched, and follows rising counts faster
+            if !touched {
+                let drift = config.drift.min(31);
+                let drift = if delta < 0 { drift / 2 } else { drift };
+                self.baseline = Some(baseline - (baseline >> drift) + (sample >> drift));
+            }
+            return None;
+        }
+
+        self.pending = self.pending.saturating_add(1);
+        if self.pending < config.debounce {
+            return None;
+        }
+        self.pending = 0;
+        self.touched = touched;
+        Some(if touched {
+            Event::Touch
+        } else {
+            Event::Release
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the baseline barely moves with a drift of 16
+    const CONFIG: Config = Config {
+        threshold: 100,
+        hysteresis: 30,
+        debounce: 1,
+        drift: 16,
+    };
+
+    fn filter(config: &Config, counts: &[u16]) -> (Filter, Vec<Option<Event>>) {
+        let mut filter = Filter::new();
+        let events = counts
+            .iter()
+            .map(|&count| filter.update(config, count))
+            .collect();
+        (filter, events)
+    }
+
+    #[test]
+    fn first_sample_is_baseline() {
+        let (filter, events) = filter(&CONFIG, &[1000]);
+        assert_eq!(events, [None]);
+        assert_eq!(filter.baseline(), Some(1000));
+        assert!(!filter.is_touched());
+    }
+
+    #[test]
+    fn threshold() {
+        let (filter, events) = filter(&CONFIG, &[1000, 901, 900]);
+        assert_eq!(events, [None, None, Some(Event::Touch)]);
+        assert!(filter.is_touched());
+    }
+
+    #[test]
+    fn hysteresis() {
+        let (filter, events) = filter(&CONFIG, &[1000, 900, 929, 930]);
+        assert_eq!(
+            events,
+            [None, Some(Event::Touch), None, Some(Event::Release)]
+        );
+        assert!(!filter.is_touched());
+    }
+
+    #[test]
+    fn debounce() {
+        let config = Config {
+            debounce: 3,
+            ..CONFIG
+        };
+        let (_, events) = filter(&config, &[1000, 900, 900, 1000, 900, 900, 900]);
+        assert_eq!(
+            events,
+            [None, None, None, None, None, None, Some(Event::Touch)]
+        );
+    }
+
+    #[test]
+    fn baseline_is_kept_while_touched() {
+        let (filter, _) = filter(&CONFIG, &[1000, 500, 500, 500]);
+        assert_eq!(filter.baseline(), Some(1000));
+    }
+
+    #[test]
+    fn drift_is_faster_on_rises() {
+        let config = Config { drift: 4, ..CONFIG };
+        let (rising, _) = filter(&config, &[1000, 1032]);
+        let (falling, _) = filter(&config, &[1000, 968]);
+        // 1/4 of the rise, 1/16 of the fall
+        assert_eq!(rising.baseline(), Some(1008));
+        assert_eq!(falling.baseline(), Some(998));
+    }
+
+    #[test]
+    fn drift_is_clamped() {
+        let config = Config {
+            drift: 255,
+            ..CONFIG
+        };
+        let (filter, _) = filter(&config, &[1000, 2000, 950]);
+        assert_eq!(filter.baseline(), Some(1000));
+    }
+}
@@ -0,0 +1,611 @@
+use crate::{
+    gpio::{
+        gpiob::{PB0, PB6},
+        Input, Output, PullUp,
+    },
+    interrupt::CoreInterrupt,
+    pfic::PficExt,
+    raw::uart0::{msr, RegisterBlock},
+    remap::{ModemPins, Remap, Uart0Pins, Uart1Pins, Uart2Pins, Uart3Pins},
+    sys::SysExt,
+    Pfic, Sys, Uart0, Uart1, Uart2, Uart3,
+};
+use core::{future::poll_fn, task::Poll};
+use embassy_sync::waitqueue::AtomicWaker;
+use embassy_time::{with_timeout, Duration};
+
+const FIFO_SIZE: u8 = 8;
+
+static RX_WAKERS: [AtomicWaker; 4] = [const { AtomicWaker::new() }; 4];
+static TX_WAKERS: [AtomicWaker; 4] = [const { AtomicWaker::new() }; 4];
+static MODEM_WAKER: AtomicWaker = AtomicWaker::new();
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum WordSize {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    Mark,
+    Space,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+pub struct Config {
+    pub baudrate: u32,
+    pub word_size: WordSize,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            baudrate: 115_200,
+            word_size: WordSize::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum FlowControl {
+    /// RTS and CTS are handled by the hardware
+    Auto,
+    /// RTS is set by the application, CTS is only reported
+    Manual,
+}
+
+pub struct RtsCtsPins {
+    pub cts: PB0<Input<PullUp>>,
+    pub rts: PB6<Output>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ModemStatus {
+    pub cts: bool,
+    pub dsr: bool,
+    pub ri: bool,
+    pub dcd: bool,
+}
+
+impl From<&msr::R> for ModemStatus {
+    fn from(msr: &msr::R) -> Self {
+        Self {
+            cts: msr.msr_cts().bit(),
+            dsr: msr.msr_dsr().bit(),
+            ri: msr.msr_ri().bit(),
+            dcd: msr.msr_dcd().bit(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    Overrun,
+    Parity,
+    Framing,
+    Break,
+}
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            Error::Parity | Error::Framing => embedded_io::ErrorKind::InvalidData,
+            Error::Overrun | Error::Break => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
+pub struct Uart<T: Instance> {
+    raw: T,
+    pins: T::Pins,
+    modem: T::Modem,
+}
+
+impl<T: Instance> Uart<T> {
+    pub fn new(raw: T, pins: T::Pins, sys: &Sys, pfic: &Pfic, config: Config) -> Self {
+        pins.remap();
+
+        let regs = T::regs();
+        // the divisor is rounded to the nearest baudrate and kept within its 16 bits
+        let dl = ((sys.fsys() * 10 / 8 / config.baudrate.max(1) + 5) / 10).clamp(1, u16::MAX as u32);
+        regs.div().write(|w| unsafe { w.div().bits(1) });
+        regs.dl().write(|w| unsafe { w.dl().bits(dl as u16) });
+        regs.fcr().write(|w| unsafe {
+            w.fcr_fifo_trig()
+                .bits(0b10)
+                .fcr_tx_fifo_clr()
+                .set_bit()
+                .fcr_rx_fifo_clr()
+                .set_bit()
+                .fcr_fifo_en()
+                .set_bit()
+        });
+        regs.lcr().write(|w| unsafe {
+            w.lcr_word_sz()
+                .bits(config.word_size as u8)
+                .lcr_stop_bit()
+                .bit(config.stop_bits == StopBits::Two)
+                .lcr_par_en()
+                .bit(config.parity != Parity::None)
+                .lcr_par_mod()
+                .bits(match config.parity {
+                    Parity::None | Parity::Odd => 0b00,
+                    Parity::Even => 0b01,
+                    Parity::Mark => 0b10,
+                    Parity::Space => 0b11,
+                })
+        });
+        regs.ier().write(|w| w.ier_txd_en().set_bit());
+        regs.mcr().write(|w| w.mcr_int_oe().set_bit());
+
+        pfic.enable(T::INTERRUPT, None);
+
+        Self {
+            raw,
+            pins,
+            modem: Default::default(),
+        }
+    }
+
+    fn read_fifo(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let regs = T::regs();
+
+        // line status errors are cleared on read
+        let lsr = regs.lsr().read();
+        if lsr.lsr_over_err().bit() {
+            return Err(Error::Overrun);
+        }
+        if lsr.lsr_par_err().bit() {
+            return Err(Error::Parity);
+        }
+        if lsr.lsr_frame_err().bit() {
+            return Err(Error::Framing);
+        }
+        if lsr.lsr_break_err().bit() {
+            return Err(Error::Break);
+        }
+
+        let len = buf.len().min(regs.rfc().read().bits() as usize);
+        for byte in &mut buf[..len] {
+            *byte = regs.rbr().read().bits();
+        }
+        Ok(len)
+    }
+
+    fn write_fifo(&mut self, buf: &[u8]) -> usize {
+        let regs = T::regs();
+
+        let len = buf
+            .len()
+            .min((FIFO_SIZE - regs.tfc().read().bits()) as usize);
+        for &byte in &buf[..len] {
+            regs.thr().write(|w| unsafe { w.bits(byte) });
+        }
+        len
+    }
+
+    pub fn blocking_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let len = self.read_fifo(buf)?;
+            if len != 0 {
+                return Ok(len);
+            }
+        }
+    }
+
+    pub fn blocking_write(&mut self, buf: &[u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+
+        loop {
+            let len = self.write_fifo(buf);
+            if len != 0 {
+                return len;
+            }
+        }
+    }
+
+    pub fn blocking_flush(&mut self) {
+        while T::regs().lsr().read().lsr_tx_all_emp().bit_is_clear() {}
+    }
+
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        poll_fn(|cx| {
+            RX_WAKERS[T::INDEX].register(cx.waker());
+            match self.read_fifo(buf) {
+                Ok(0) => {
+                    T::regs()
+                        .ier()
+                        .modify(|_, w| w.ier_recv_rdy().set_bit().ier_line_stat().set_bit());
+                    Poll::Pending
+                }
+                res => Poll::Ready(res),
+            }
+        })
+        .await
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+
+        poll_fn(|cx| {
+            TX_WAKERS[T::INDEX].register(cx.waker());
+            match self.write_fifo(buf) {
+                0 => {
+                    T::regs().ier().modify(|_, w| w.ier_thr_empty().set_bit());
+                    Poll::Pending
+                }
+                len => Poll::Ready(len),
+            }
+        })
+        .await
+    }
+
+    pub async fn flush(&mut self) {
+        poll_fn(|cx| {
+            TX_WAKERS[T::INDEX].register(cx.waker());
+            if T::regs().lsr().read().lsr_tx_fifo_emp().bit() {
+                Poll::Ready(())
+            } else {
+                T::regs().ier().modify(|_, w| w.ier_thr_empty().set_bit());
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // the last byte is still being shifted out
+        self.blocking_flush();
+    }
+}
+
+impl<T: Instance<Modem = ()>> Uart<T> {
+    pub fn free(self) -> (T, T::Pins) {
+        T::regs().ier().write(|w| w.ier_reset().set_bit());
+        (self.raw, self.pins)
+    }
+}
+
+// only UART0 has modem control
+impl Uart<Uart0> {
+    /// The modem pins are returned if they were given to [`Uart::with_rts_cts`] or
+    /// [`Uart::with_dsr_dtr`].
+    pub fn free(self) -> (Uart0, Uart0Pins, Option<RtsCtsPins>, Option<ModemPins>) {
+        self.raw.ier().write(|w| w.ier_reset().set_bit());
+        let (rts_cts, dsr_dtr) = self.modem;
+        (self.raw, self.pins, rts_cts, dsr_dtr)
+    }
+
+    pub fn with_rts_cts(mut self, pins: RtsCtsPins, flow_control: FlowControl) -> Self {
+        self.raw.ier().modify(|_, w| w.ier_rts_en().set_bit());
+        self.raw.mcr().modify(|_, w| {
+            w.mcr_au_flow_en()
+                .bit(flow_control == FlowControl::Auto)
+                .mcr_rts()
+                .set_bit()
+        });
+        self.modem.0 = Some(pins);
+        self
+    }
+
+    pub fn with_dsr_dtr(mut self, pins: ModemPins) -> Self {
+        pins.remap();
+
+        self.raw.ier().modify(|_, w| w.ier_dtr_en().set_bit());
+        self.modem.1 = Some(pins);
+        self
+    }
+
+    pub fn set_rts(&mut self, active: bool) {
+        self.raw.mcr().modify(|_, w| w.mcr_rts().bit(active));
+    }
+
+    pub fn set_dtr(&mut self, active: bool) {
+        self.raw.mcr().modify(|_, w| w.mcr_dtr().bit(active));
+    }
+
+    pub fn modem_status(&self) -> ModemStatus {
+        ModemStatus::from(&self.raw.msr().read())
+    }
+
+    pub async fn wait_for_modem_change(&mut self) -> ModemStatus {
+        poll_fn(|cx| {
+            MODEM_WAKER.register(cx.waker());
+
+            // changes are cleared on read
+            let msr = self.raw.msr().read();
+            if msr.msr_cts_chg().bit()
+                || msr.msr_dsr_chg().bit()
+                || msr.msr_ri_chg().bit()
+                || msr.msr_dcd_chg().bit()
+            {
+                Poll::Ready(ModemStatus::from(&msr))
+            } else {
+                self.raw.ier().modify(|_, w| w.ier_modem_chg().set_bit());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+pub struct Rs485 {
+    uart: Uart<Uart0>,
+    pins: ModemPins,
+    // restored on free
+    dtr_en: bool,
+    fifo_trig: u8,
+    lcr: u8,
+}
+
+impl Rs485 {
+    // TNOW is output on the DTR pin and enables the transceiver while transmitting
+    pub fn new(uart: Uart<Uart0>, pins: ModemPins) -> Self {
+        pins.remap();
+
+        let dtr_en = uart.raw.ier().read().ier_dtr_en().bit();
+        let fifo_trig = uart.raw.fcr().read().fcr_fifo_trig().bits();
+        let lcr = uart.raw.lcr().read().bits();
+
+        uart.raw.ier().modify(|_, w| w.ier_dtr_en().set_bit());
+        uart.raw
+            .mcr()
+            .modify(|_, w| w.mcr_half().set_bit().mcr_tnow().set_bit());
+        // trigger on every byte, as the receiver timeout is longer than the idle time
+        uart.raw
+            .fcr()
+            .modify(|_, w| unsafe { w.fcr_fifo_trig().bits(0b00) });
+
+        Self {
+            uart,
+            pins,
+            dtr_en,
+            fifo_trig,
+            lcr,
+        }
+    }
+
+    pub fn free(self) -> (Uart<Uart0>, ModemPins) {
+        self.uart.raw.adr().write(|w| unsafe { w.bits(0xFF) });
+        self.uart.raw.mcr().modify(|_, w| {
+            w.mcr_half()
+                .clear_bit()
+                .mcr_tnow()
+                .clear_bit()
+                .mcr_dtr()
+                .clear_bit()
+        });
+        self.uart
+            .raw
+            .fcr()
+            .modify(|_, w| unsafe { w.fcr_fifo_trig().bits(self.fifo_trig) });
+        self.uart.raw.lcr().write(|w| unsafe { w.bits(self.lcr) });
+        self.uart
+            .raw
+            .ier()
+            .modify(|_, w| w.ier_dtr_en().bit(self.dtr_en));
+        (self.uart, self.pins)
+    }
+
+    /// Only receive frames addressed to this address, the 9th bit (parity) marks address bytes.
+    pub fn set_address(&mut self, address: Option<u8>) {
+        match address {
+            Some(address) => {
+                self.uart.raw.lcr().modify(|_, w| unsafe {
+                    w.lcr_word_sz()
+                        .bits(WordSize::Eight as u8)
+                        .lcr_par_en()
+                        .set_bit()
+                        .lcr_par_mod()
+                        .bits(0b11)
+                });
+                self.uart.raw.adr().write(|w| unsafe { w.bits(address) });
+            }
+            None => {
+                self.uart.raw.adr().write(|w| unsafe { w.bits(0xFF) });
+                // back to the configured word size and parity
+                self.uart.raw.lcr().write(|w| unsafe { w.bits(self.lcr) });
+            }
+        }
+    }
+
+    /// Send an address byte with the 9th bit (parity) set, following bytes are sent as data.
+    pub async fn send_address(&mut self, address: u8) {
+        self.uart.flush().await;
+        self.uart.raw.lcr().modify(|_, w| unsafe {
+            w.lcr_word_sz()
+                .bits(WordSize::Eight as u8)
+                .lcr_par_en()
+                .set_bit()
+                .lcr_par_mod()
+                .bits(0b10)
+        });
+        self.uart.write(&[address]).await;
+        self.uart.flush().await;
+        self.uart
+            .raw
+            .lcr()
+            .modify(|_, w| unsafe { w.lcr_par_mod().bits(0b11) });
+    }
+
+    pub async fn send(&mut self, frame: &[u8]) {
+        let mut frame = frame;
+        while !frame.is_empty() {
+            let len = self.uart.write(frame).await;
+            frame = &frame[len..];
+        }
+        self.uart.flush().await;
+    }
+
+    /// Receive a frame, which ends when the line is idle for longer than `idle` or the buffer is
+    /// full.
+    pub async fn receive(&mut self, buf: &mut [u8], idle: Duration) -> Result<usize, Error> {
+        let mut len = self.uart.read(buf).await?;
+        while len < buf.len() {
+            match with_timeout(idle, self.uart.read(&mut buf[len..])).await {
+                Ok(res) => len += res?,
+                Err(_) => break,
+            }
+        }
+        Ok(len)
+    }
+}
+
+impl<T: Instance> embedded_io::ErrorType for Uart<T> {
+    type Error = Error;
+}
+
+impl<T: Instance> embedded_io::Read for Uart<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.blocking_read(buf)
+    }
+}
+
+impl<T: Instance> embedded_io::Write for Uart<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(self.blocking_write(buf))
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.blocking_flush();
+        Ok(())
+    }
+}
+
+impl<T: Instance> embedded_io_async::Read for Uart<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Uart::read(self, buf).await
+    }
+}
+
+impl<T: Instance> embedded_io_async::Write for Uart<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(Uart::write(self, buf).await)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Uart::flush(self).await;
+        Ok(())
+    }
+}
+
+mod sealed {
+    use crate::{interrupt::CoreInterrupt, raw::uart0::RegisterBlock, remap::Remap};
+
+    // UART1..3 have the same register layout as UART0, only without modem control
+    pub trait Instance {
+        const INTERRUPT: CoreInterrupt;
+        const INDEX: usize;
+
+        type Pins: Remap;
+        // the RTS/CTS and DSR/DTR pins of UART0
+        type Modem: Default;
+
+        fn regs() -> &'static RegisterBlock;
+    }
+}
+pub use sealed::Instance;
+
+macro_rules! instance {
+    ($Uart:ident, $Pins:ident, $Modem:ty, $INTERRUPT:ident, $index:expr) => {
+        impl Instance for $Uart {
+            const INTERRUPT: CoreInterrupt = CoreInterrupt::$INTERRUPT;
+            const INDEX: usize = $index;
+
+            type Pins = $Pins;
+            type Modem = $Modem;
+
+            fn regs() -> &'static RegisterBlock {
+                unsafe { &*($Uart::ptr() as *const RegisterBlock) }
+            }
+        }
+    };
+}
+
+instance!(
+    Uart0,
+    Uart0Pins,
+    (Option<RtsCtsPins>, Option<ModemPins>),
+    UART0,
+    0
+);
+instance!(Uart1, Uart1Pins, (), UART1, 1);
+instance!(Uart2, Uart2Pins, (), UART2, 2);
+instance!(Uart3, Uart3Pins, (), UART3, 3);
+
+fn on_interrupt<T: Instance>() {
+    let regs = T::regs();
+
+    // the line status can't be read here, as the errors are cleared on read
+    let iir = regs.iir().read();
+    if iir.iir_no_int().bit() {
+        return;
+    }
+    match iir.iir_int_mask().bits() {
+        // line status, data received or receiver timeout
+        0b0110 | 0b0100 | 0b1100 => {
+            // will be handled later
+            regs.ier()
+                .modify(|_, w| w.ier_recv_rdy().clear_bit().ier_line_stat().clear_bit());
+            RX_WAKERS[T::INDEX].wake();
+        }
+        // transmitter holding register empty
+        0b0010 => {
+            // will be handled later
+            regs.ier().modify(|_, w| w.ier_thr_empty().clear_bit());
+            TX_WAKERS[T::INDEX].wake();
+        }
+        // modem status change, only enabled on UART0
+        0b0000 => {
+            // will be handled later
+            regs.ier().modify(|_, w| w.ier_modem_chg().clear_bit());
+            MODEM_WAKER.wake();
+        }
+        _ => {}
+    }
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::UART0)]
+fn uart0() {
+    on_interrupt::<Uart0>();
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::UART1)]
+fn uart1() {
+    on_interrupt::<Uart1>();
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::UART2)]
+fn uart2() {
+    on_interrupt::<Uart2>();
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::UART3)]
+fn uart3() {
+    on_interrupt::<Uart3>();
+}
@@ -0,0 +1,149 @@
+use crate::{
+    battery,
+    interrupt::CoreInterrupt,
+    pfic::PficExt,
+    sys::{with_safe_access_mode, SysExt},
+    Pfic, Sys,
+};
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+use embassy_sync::waitqueue::AtomicWaker;
+use embassy_time::{Duration, TICK_HZ};
+
+// the counter is incremented every 131072 system clocks and overflows after 256 increments
+const CLOCKS_PER_COUNT: u64 = 131072;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+// the overflow flag is also cleared by SEV, which is issued by the executor
+static BARKED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Resets the system on overflow.
+    Reset,
+    /// Only raises an interrupt on overflow, see [`Watchdog::wait_bark`].
+    Interrupt,
+}
+
+pub struct Config {
+    pub timeout: Duration,
+    pub mode: Mode,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(250),
+            mode: Mode::Reset,
+        }
+    }
+}
+
+pub struct Watchdog<'a> {
+    sys: &'a Sys,
+    reload: u8,
+    mode: Mode,
+}
+
+impl<'a> Watchdog<'a> {
+    /// The timeout is clamped to 256 counts, which is about 560 ms at 60 MHz.
+    pub fn new(sys: &'a Sys, pfic: &Pfic, config: Config) -> Self {
+        let counts = (config.timeout.as_ticks() * sys.fsys() as u64 / CLOCKS_PER_COUNT / TICK_HZ)
+            .clamp(1, 256);
+
+        pfic.enable(CoreInterrupt::WDOG_BAT, None);
+
+        Self {
+            sys,
+            // a reload value of 0 gives the full 256 counts
+            reload: (256 - counts) as u8,
+            mode: config.mode,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.feed();
+        with_safe_access_mode(|| {
+            self.sys.rst_wdog_ctrl().modify(|_, w| {
+                w.software_reset()
+                    .clear_bit()
+                    .wdog_rst_en()
+                    .bit(self.mode == Mode::Reset)
+                    .wdog_int_flag()
+                    .set_bit()
+            });
+        });
+    }
+
+    pub fn stop(&mut self) {
+        with_safe_access_mode(|| {
+            self.sys.rst_wdog_ctrl().modify(|_, w| {
+                w.software_reset()
+                    .clear_bit()
+                    .wdog_rst_en()
+                    .clear_bit()
+                    .wdog_int_en()
+                    .clear_bit()
+                    .wdog_int_flag()
+                    .set_bit()
+            });
+        });
+    }
+
+    pub fn feed(&mut self) {
+        self.sys
+            .wdog_count()
+            .write(|w| unsafe { w.wdog_count().bits(self.reload) });
+    }
+
+    /// Waits for the next overflow, the watchdog has to be fed afterwards.
+    pub async fn wait_bark(&mut self) {
+        BARKED.store(false, Ordering::Relaxed);
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if BARKED.load(Ordering::Relaxed) {
+                Poll::Ready(())
+            } else {
+                with_safe_access_mode(|| {
+                    // an overflow that happened before is kept pending
+                    self.sys.rst_wdog_ctrl().modify(|_, w| {
+                        w.software_reset()
+                            .clear_bit()
+                            .wdog_int_en()
+                            .set_bit()
+                            .wdog_int_flag()
+                            .clear_bit()
+                    });
+                });
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[riscv_rt::core_interrupt(CoreInterrupt::WDOG_BAT)]
+fn wdog_bat() {
+    let sys = unsafe { Sys::steal() };
+
+    if sys.rst_wdog_ctrl().read().wdog_int_flag().bit() {
+        // will be handled later
+        with_safe_access_mode(|| {
+            sys.rst_wdog_ctrl().modify(|_, w| {
+                w.software_reset()
+                    .clear_bit()
+                    .wdog_int_en()
+                    .clear_bit()
+                    .wdog_int_flag()
+                    .set_bit()
+            });
+        });
+        BARKED.store(true, Ordering::Relaxed);
+        WAKER.wake();
+    }
+
+    battery::on_interrupt(&sys);
+}